@@ -0,0 +1,161 @@
+use anchor_lang::prelude::*;
+use anchor_lang::pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::{program::invoke_signed, system_instruction};
+
+use crate::{program_error, CommonError};
+
+pub const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+// `TransferChecked` of the token program, it rejects a source account of another mint
+const TRANSFER_CHECKED: u8 = 12;
+// offset of `decimals` in a token program mint account
+const MINT_DECIMALS_OFFSET: usize = 44;
+
+/// Spending state of an allowance, the `Allowance` accounts of the programs keep these fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    pub amount: u64,       // spendable per period
+    pub spent: u64,        // spent in the current period
+    pub period: i64,       // length of a period in seconds, 0 for an allowance that never resets
+    pub period_start: i64, // unix timestamp the current period started at
+}
+
+impl Budget {
+    /// Moves on to the period `now` is in and books `amount` against it.
+    pub fn spend(&mut self, amount: u64, now: i64) -> std::result::Result<(), CommonError> {
+        if self.period > 0 && now >= self.period_start.saturating_add(self.period) {
+            // periods stay aligned to the first one, unused allowance does not carry over
+            let elapsed = (now - self.period_start) / self.period;
+            self.period_start += elapsed * self.period;
+            self.spent = 0;
+        }
+
+        self.spent = self
+            .spent
+            .checked_add(amount)
+            .filter(|spent| *spent <= self.amount)
+            .ok_or(CommonError::AllowanceExceeded)?;
+        Ok(())
+    }
+}
+
+pub fn transfer_lamports<'info>(
+    multisig_pda: &AccountInfo<'info>,
+    recipient: &AccountInfo<'info>,
+    amount: u64,
+    signer: &[&[&[u8]]],
+) -> Result<()> {
+    let ix = system_instruction::transfer(multisig_pda.key, recipient.key, amount);
+    invoke_signed(&ix, &[multisig_pda.clone(), recipient.clone()], signer)?;
+    Ok(())
+}
+
+/// Transfers `amount` of `mint` from a token account of the multisig pda, mismatched token
+/// accounts are reported as the program error `E`.
+pub fn transfer_tokens<'info, E>(
+    vault: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    recipient: &AccountInfo<'info>,
+    multisig_pda: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    amount: u64,
+    signer: &[&[&[u8]]],
+) -> Result<()>
+where
+    E: From<CommonError>,
+    anchor_lang::error::Error: From<E>,
+{
+    if *token_program.key != TOKEN_PROGRAM_ID || *mint.owner != TOKEN_PROGRAM_ID {
+        return Err(program_error::<E>(CommonError::InvalidAllowanceAccounts));
+    }
+    let decimals = *mint
+        .try_borrow_data()?
+        .get(MINT_DECIMALS_OFFSET)
+        .ok_or_else(|| program_error::<E>(CommonError::InvalidAllowanceAccounts))?;
+
+    let mut data = vec![TRANSFER_CHECKED];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(decimals);
+
+    let ix = Instruction {
+        program_id: TOKEN_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*vault.key, false),
+            AccountMeta::new_readonly(*mint.key, false),
+            AccountMeta::new(*recipient.key, false),
+            AccountMeta::new_readonly(*multisig_pda.key, true),
+        ],
+        data,
+    };
+    invoke_signed(
+        &ix,
+        &[
+            vault.clone(),
+            mint.clone(),
+            recipient.clone(),
+            multisig_pda.clone(),
+            token_program.clone(),
+        ],
+        signer,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowance(period: i64) -> Budget {
+        Budget {
+            amount: 100,
+            spent: 0,
+            period,
+            period_start: 1_000,
+        }
+    }
+
+    #[test]
+    fn spends_up_to_the_amount() {
+        let mut allowance = allowance(0);
+        allowance.spend(60, 1_000).unwrap();
+        allowance.spend(40, 1_000).unwrap();
+        assert!(allowance.spend(1, 1_000).is_err());
+        assert_eq!(allowance.spent, 100);
+    }
+
+    #[test]
+    fn never_resets_without_a_period() {
+        let mut allowance = allowance(0);
+        allowance.spend(100, 1_000).unwrap();
+        assert!(allowance.spend(1, i64::MAX).is_err());
+    }
+
+    #[test]
+    fn resets_once_the_period_is_over() {
+        let mut allowance = allowance(60);
+        allowance.spend(100, 1_000).unwrap();
+        assert!(allowance.spend(1, 1_059).is_err());
+
+        allowance.spend(100, 1_060).unwrap();
+        assert_eq!(allowance.period_start, 1_060);
+    }
+
+    #[test]
+    fn keeps_periods_aligned() {
+        let mut allowance = allowance(60);
+        allowance.spend(10, 1_000).unwrap();
+
+        // several periods later the current one started at a multiple of the period
+        allowance.spend(10, 1_000 + 60 * 5 + 30).unwrap();
+        assert_eq!(allowance.period_start, 1_300);
+        assert_eq!(allowance.spent, 10);
+    }
+
+    #[test]
+    fn rejects_overflowing_amounts() {
+        let mut allowance = allowance(0);
+        allowance.spend(1, 1_000).unwrap();
+        assert!(allowance.spend(u64::MAX, 1_000).is_err());
+        assert_eq!(allowance.spent, 1);
+    }
+}
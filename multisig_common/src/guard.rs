@@ -0,0 +1,114 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program;
+use anchor_lang::solana_program::instruction::Instruction;
+
+use crate::refund::Refund;
+use crate::MultiSigInstruction;
+
+/// Anchor discriminator of `check_transaction`, called after the approvals are verified and
+/// before the first instruction runs.
+pub const CHECK_TRANSACTION: [u8; 8] = [206, 211, 54, 137, 171, 96, 56, 150];
+/// Anchor discriminator of `check_after_execution`, called once every instruction ran.
+pub const CHECK_AFTER_EXECUTION: [u8; 8] = [119, 222, 172, 63, 101, 78, 29, 108];
+
+/// Arguments of both hooks, an anchor guard declares them as
+/// `(config: Pubkey, nonce: u64, instructions: Vec<MultiSigInstruction>, refund: Option<Refund>)`.
+pub fn hook_args(
+    config: Pubkey,
+    nonce: u64,
+    instructions: &[MultiSigInstruction],
+    refund: Option<&Refund>,
+) -> Result<Vec<u8>> {
+    Ok((config, nonce, instructions, refund).try_to_vec()?)
+}
+
+/// Calls `hook` on the guard with the config and the multisig pda as read-only accounts.
+/// The guard is invoked without the multisig seeds, it can veto a transaction by failing
+/// but never act for the multisig.
+pub fn check<'info>(
+    guard: &AccountInfo<'info>,
+    hook: [u8; 8],
+    args: &[u8],
+    config: &AccountInfo<'info>,
+    multisig_pda: &AccountInfo<'info>,
+) -> Result<()> {
+    let ix = Instruction {
+        program_id: guard.key(),
+        accounts: vec![
+            AccountMeta::new_readonly(config.key(), false),
+            AccountMeta::new_readonly(multisig_pda.key(), false),
+        ],
+        data: [&hook[..], args].concat(),
+    };
+
+    solana_program::program::invoke(&ix, &[config.clone(), multisig_pda.clone(), guard.clone()])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionAccount;
+    use anchor_lang::solana_program::hash;
+
+    #[test]
+    fn discriminators_match_the_hook_names() {
+        let discriminator = |name: &str| -> [u8; 8] {
+            hash::hash(format!("global:{}", name).as_bytes()).to_bytes()[..8]
+                .try_into()
+                .unwrap()
+        };
+
+        assert_eq!(CHECK_TRANSACTION, discriminator("check_transaction"));
+        assert_eq!(
+            CHECK_AFTER_EXECUTION,
+            discriminator("check_after_execution")
+        );
+    }
+
+    // the hand rolled args have to decode as the declared anchor arguments
+    #[test]
+    fn args_decode_as_declared() {
+        let instructions = vec![MultiSigInstruction {
+            program_id: Pubkey::new_from_array([8; 32]),
+            accounts: vec![TransactionAccount {
+                pubkey: Pubkey::new_from_array([7; 32]),
+                is_signer: true,
+                is_writable: false,
+            }],
+            data: vec![0xaa, 0xbb],
+        }];
+        let args = hook_args(Pubkey::new_from_array([1; 32]), 5, &instructions, None).unwrap();
+
+        let (config, nonce, decoded, refund): (
+            Pubkey,
+            u64,
+            Vec<MultiSigInstruction>,
+            Option<Refund>,
+        ) = AnchorDeserialize::try_from_slice(&args).unwrap();
+        assert_eq!(config, Pubkey::new_from_array([1; 32]));
+        assert_eq!(nonce, 5);
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].program_id, instructions[0].program_id);
+        assert_eq!(decoded[0].data, instructions[0].data);
+        assert!(refund.is_none());
+    }
+
+    // a guard can limit what relayers are paid, so it sees the refund the owners signed
+    #[test]
+    fn args_carry_the_refund() {
+        let refund = Refund {
+            amount: 5000,
+            mint: Pubkey::new_from_array([3; 32]),
+            receiver: Pubkey::new_from_array([4; 32]),
+        };
+        let args = hook_args(Pubkey::new_from_array([1; 32]), 5, &[], Some(&refund)).unwrap();
+
+        let (_, _, _, decoded): (Pubkey, u64, Vec<MultiSigInstruction>, Option<Refund>) =
+            AnchorDeserialize::try_from_slice(&args).unwrap();
+        let decoded = decoded.unwrap();
+        assert_eq!(decoded.amount, refund.amount);
+        assert_eq!(decoded.mint, refund.mint);
+        assert_eq!(decoded.receiver, refund.receiver);
+    }
+}
@@ -0,0 +1,51 @@
+//! Owner agnostic parts of the multisig programs.
+//!
+//! Both programs hash, guard, limit and refund transactions the same way, only their owners and
+//! how the owners' signatures are verified differ. Failed checks surface as a [`CommonError`],
+//! which each program maps onto its own error codes.
+
+use anchor_lang::prelude::*;
+
+pub mod allowance;
+pub mod guard;
+pub mod policy;
+pub mod refund;
+pub mod tx_hash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommonError {
+    /// the transaction hash version is not supported
+    InvalidHashVersion,
+    /// the amount exceeds the remaining allowance
+    AllowanceExceeded,
+    /// the token accounts do not match the allowance
+    InvalidAllowanceAccounts,
+    /// the refund or the refund accounts do not match the signed refund
+    InvalidRefund,
+    /// a threshold is out of range
+    InvalidThreshold,
+}
+
+/// Reports `err` as the program error `E` maps it to, for the helpers that also invoke other
+/// programs and therefore return anchor errors.
+fn program_error<E>(err: CommonError) -> anchor_lang::error::Error
+where
+    E: From<CommonError>,
+    anchor_lang::error::Error: From<E>,
+{
+    E::from(err).into()
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize, Clone, Copy)]
+pub struct TransactionAccount {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize)]
+pub struct MultiSigInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<TransactionAccount>,
+    pub data: Vec<u8>,
+}
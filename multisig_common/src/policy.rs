@@ -0,0 +1,228 @@
+use anchor_lang::prelude::*;
+
+use crate::{CommonError, MultiSigInstruction};
+
+/// Threshold for the instructions to `program_id` whose data starts with `prefix`, e.g. a
+/// higher one for the upgrade instruction of the loader or a lower one for small transfers.
+#[derive(AnchorDeserialize, AnchorSerialize, Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub program_id: Pubkey,
+    pub prefix: Vec<u8>, // leading bytes of the instruction data, empty for every instruction
+    pub threshold: u8,
+}
+
+impl Policy {
+    /// Long enough for an anchor discriminator.
+    pub const MAX_PREFIX: usize = 8;
+    pub const SPACE: usize = 32 + 4 + Self::MAX_PREFIX + 1;
+
+    fn matches(&self, instruction: &MultiSigInstruction) -> bool {
+        self.program_id == instruction.program_id && instruction.data.starts_with(&self.prefix)
+    }
+}
+
+/// The thresholds of a config, borrowed from its `MultiSigConfig`.
+pub struct Thresholds<'a> {
+    pub threshold: u8,       // default for every instruction
+    pub admin_threshold: u8, // instructions to the multisig program itself, 0 for the default
+    pub policies: &'a [Policy],
+}
+
+/// Threshold `instruction` needs: the admin threshold if it calls the multisig program itself
+/// and one is set, else the policy with the longest matching prefix, else the config's.
+pub fn instruction_threshold(
+    config: &Thresholds,
+    program_id: &Pubkey,
+    instruction: &MultiSigInstruction,
+) -> u8 {
+    if instruction.program_id == *program_id && config.admin_threshold != 0 {
+        return config.admin_threshold;
+    }
+    config
+        .policies
+        .iter()
+        .filter(|policy| policy.matches(instruction))
+        .max_by_key(|policy| policy.prefix.len())
+        .map_or(config.threshold, |policy| policy.threshold)
+}
+
+/// A transaction needs the highest threshold of its instructions.
+pub fn required_threshold(
+    config: &Thresholds,
+    program_id: &Pubkey,
+    instructions: &[MultiSigInstruction],
+) -> u8 {
+    instructions
+        .iter()
+        .map(|instruction| instruction_threshold(config, program_id, instruction))
+        .max()
+        .unwrap_or(config.threshold)
+}
+
+/// Threshold a vault with its own owners needs for `instructions`: its own, unless a policy or
+/// the admin threshold asks for more.
+pub fn required_vault_threshold(
+    config: &Thresholds,
+    vault_threshold: u8,
+    program_id: &Pubkey,
+    instructions: &[MultiSigInstruction],
+) -> u8 {
+    let vault = Thresholds {
+        threshold: vault_threshold,
+        ..*config
+    };
+    required_threshold(&vault, program_id, instructions).max(vault_threshold)
+}
+
+/// Checks that every policy and the admin threshold can be reached with `voting_weight`, and that
+/// the admin threshold, if set, is not below the default, otherwise owners could pass a config
+/// change with fewer approvals than any other transaction needs.
+pub fn validate(config: &Thresholds, voting_weight: u32) -> std::result::Result<(), CommonError> {
+    let admin_threshold_valid = config.admin_threshold == 0
+        || (config.admin_threshold >= config.threshold
+            && config.admin_threshold as u32 <= voting_weight);
+    let policies_valid = config
+        .policies
+        .iter()
+        .all(|policy| policy.threshold > 0 && policy.threshold as u32 <= voting_weight);
+    if !admin_threshold_valid || !policies_valid {
+        return Err(CommonError::InvalidThreshold);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(policies: &[Policy], admin_threshold: u8) -> Thresholds<'_> {
+        Thresholds {
+            threshold: 2,
+            admin_threshold,
+            policies,
+        }
+    }
+
+    fn instruction(program_id: Pubkey, data: &[u8]) -> MultiSigInstruction {
+        MultiSigInstruction {
+            program_id,
+            accounts: vec![],
+            data: data.to_vec(),
+        }
+    }
+
+    fn policy(program_id: Pubkey, prefix: &[u8], threshold: u8) -> Policy {
+        Policy {
+            program_id,
+            prefix: prefix.to_vec(),
+            threshold,
+        }
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let program = Pubkey::new_unique();
+        let policies = [policy(program, &[], 3), policy(program, &[2, 0], 1)];
+        let config = config(&policies, 0);
+
+        let multisig = Pubkey::new_unique();
+        assert_eq!(
+            instruction_threshold(&config, &multisig, &instruction(program, &[2, 0, 0, 0])),
+            1
+        );
+        assert_eq!(
+            instruction_threshold(&config, &multisig, &instruction(program, &[3, 0, 0, 0])),
+            3
+        );
+        assert_eq!(
+            instruction_threshold(&config, &multisig, &instruction(multisig, &[2, 0])),
+            2
+        );
+    }
+
+    #[test]
+    fn admin_threshold_covers_the_multisig() {
+        let multisig = Pubkey::new_unique();
+        let config = config(&[], 3);
+
+        assert_eq!(
+            instruction_threshold(&config, &multisig, &instruction(multisig, &[1])),
+            3
+        );
+        assert_eq!(
+            instruction_threshold(&config, &multisig, &instruction(Pubkey::new_unique(), &[1])),
+            2
+        );
+    }
+
+    #[test]
+    fn batch_needs_the_highest_threshold() {
+        let program = Pubkey::new_unique();
+        let multisig = Pubkey::new_unique();
+        let policies = [policy(program, &[], 1)];
+        let config = config(&policies, 3);
+
+        assert_eq!(
+            required_threshold(&config, &multisig, &[instruction(program, &[])]),
+            1
+        );
+        assert_eq!(
+            required_threshold(
+                &config,
+                &multisig,
+                &[instruction(program, &[]), instruction(multisig, &[])]
+            ),
+            3
+        );
+    }
+
+    #[test]
+    fn policies_only_raise_a_vault_threshold() {
+        let program = Pubkey::new_unique();
+        let multisig = Pubkey::new_unique();
+        let policies = [policy(program, &[1], 1), policy(program, &[2], 4)];
+        let config = config(&policies, 0);
+
+        assert_eq!(
+            required_vault_threshold(&config, 3, &multisig, &[instruction(program, &[0])]),
+            3
+        );
+        assert_eq!(
+            required_vault_threshold(&config, 3, &multisig, &[instruction(program, &[1])]),
+            3
+        );
+        assert_eq!(
+            required_vault_threshold(&config, 3, &multisig, &[instruction(program, &[2])]),
+            4
+        );
+    }
+
+    #[test]
+    fn admin_threshold_stays_at_or_above_the_default() {
+        assert_eq!(validate(&config(&[], 0), 3), Ok(()));
+        assert_eq!(validate(&config(&[], 2), 3), Ok(()));
+        assert_eq!(validate(&config(&[], 3), 3), Ok(()));
+        assert_eq!(
+            validate(&config(&[], 1), 3),
+            Err(CommonError::InvalidThreshold)
+        );
+        assert_eq!(
+            validate(&config(&[], 4), 3),
+            Err(CommonError::InvalidThreshold)
+        );
+    }
+
+    #[test]
+    fn policies_stay_reachable() {
+        let program = Pubkey::new_unique();
+        assert_eq!(validate(&config(&[policy(program, &[], 1)], 0), 3), Ok(()));
+        assert_eq!(
+            validate(&config(&[policy(program, &[], 0)], 0), 3),
+            Err(CommonError::InvalidThreshold)
+        );
+        assert_eq!(
+            validate(&config(&[policy(program, &[], 4)], 0), 3),
+            Err(CommonError::InvalidThreshold)
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use anchor_lang::prelude::*;
+
+use crate::allowance;
+use crate::{program_error, CommonError};
+
+/// Fee refund the multisig pda pays once a transaction executed, similar to Safe's gas
+/// refund. It is part of what the owners sign, so a relayer can only claim what was approved.
+#[derive(AnchorDeserialize, AnchorSerialize, Clone)]
+pub struct Refund {
+    pub amount: u64,
+    pub mint: Pubkey,     // `Pubkey::default()` for SOL
+    pub receiver: Pubkey, // `Pubkey::default()` for whoever relays a SOL refund
+}
+
+impl Refund {
+    // appended to the canonical hash payload after the instructions
+    pub fn encode(&self, payload: &mut Vec<u8>) {
+        payload.extend_from_slice(&self.amount.to_le_bytes());
+        payload.extend_from_slice(&self.mint.to_bytes());
+        payload.extend_from_slice(&self.receiver.to_bytes());
+    }
+}
+
+/// Pays `refund` from the multisig pda to `receiver`, the token accounts are only needed for
+/// token refunds. Accounts that do not match the refund are reported as the program error `E`.
+pub fn pay<'info, E>(
+    refund: &Refund,
+    multisig_pda: &AccountInfo<'info>,
+    receiver: Option<&AccountInfo<'info>>,
+    vault: Option<&AccountInfo<'info>>,
+    mint: Option<&AccountInfo<'info>>,
+    token_program: Option<&AccountInfo<'info>>,
+    signer: &[&[&[u8]]],
+) -> Result<()>
+where
+    E: From<CommonError>,
+    anchor_lang::error::Error: From<E>,
+{
+    let invalid = || program_error::<E>(CommonError::InvalidRefund);
+
+    let receiver = receiver.ok_or_else(invalid)?;
+    let receiver_matches = if refund.receiver == Pubkey::default() {
+        // the relayer has to sign, otherwise anyone could redirect the refund to themselves
+        receiver.is_signer && refund.mint == Pubkey::default()
+    } else {
+        *receiver.key == refund.receiver
+    };
+    if !receiver_matches {
+        return Err(invalid());
+    }
+
+    if refund.mint == Pubkey::default() {
+        return allowance::transfer_lamports(multisig_pda, receiver, refund.amount, signer);
+    }
+
+    let (Some(vault), Some(mint), Some(token_program)) = (vault, mint, token_program) else {
+        return Err(invalid());
+    };
+    if *mint.key != refund.mint {
+        return Err(invalid());
+    }
+    allowance::transfer_tokens::<E>(
+        vault,
+        mint,
+        receiver,
+        multisig_pda,
+        token_program,
+        refund.amount,
+        signer,
+    )
+}
@@ -0,0 +1,366 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::keccak;
+
+use crate::refund::Refund;
+use crate::{CommonError, MultiSigInstruction};
+
+/// Original layout, fields are concatenated without length prefixes.
+/// Kept for backwards compatibility, only for a single instruction without a refund since
+/// nothing would separate a second instruction or a refund from the data before it.
+pub const TX_HASH_V1: u8 = 1;
+/// Canonical layout, every variable length field is preceded by its u32 length.
+pub const TX_HASH_V2: u8 = 2;
+
+// binds a signature to one deployment of the program on one cluster and one layout
+pub struct TxHashDomain {
+    pub program_id: Pubkey,
+    pub chain_id: [u8; 32],
+    pub version: u8,
+}
+
+/// Prefix of cancel messages, its first byte is never a hash version so a cancel message
+/// can not be confused with a transaction hash preimage.
+pub const CANCEL_TAG: &[u8] = b"cancel";
+
+pub fn validate_version(version: u8) -> Result<(), CommonError> {
+    if version != TX_HASH_V1 && version != TX_HASH_V2 {
+        return Err(CommonError::InvalidHashVersion);
+    }
+    Ok(())
+}
+
+pub fn create_multi_sig_tx_hash(
+    domain: &TxHashDomain,
+    multisig_pda: Pubkey,
+    nonce: u64,
+    valid_after: i64,
+    valid_until: i64,
+    instructions: &[MultiSigInstruction],
+    refund: Option<&Refund>,
+) -> Result<[u8; 32], CommonError> {
+    validate_version(domain.version)?;
+    if domain.version == TX_HASH_V1 {
+        if instructions.len() != 1 {
+            return Err(CommonError::InvalidHashVersion);
+        }
+        if refund.is_some() {
+            return Err(CommonError::InvalidRefund);
+        }
+    }
+
+    let mut payload = Vec::new();
+
+    payload.push(domain.version);
+    payload.extend_from_slice(&domain.program_id.to_bytes());
+    payload.extend_from_slice(&domain.chain_id);
+
+    payload.extend_from_slice(&multisig_pda.to_bytes());
+
+    payload.extend_from_slice(&nonce.to_le_bytes());
+
+    payload.extend_from_slice(&valid_after.to_le_bytes());
+    payload.extend_from_slice(&valid_until.to_le_bytes());
+
+    if domain.version == TX_HASH_V1 {
+        encode_instruction_v1(&mut payload, &instructions[0]);
+    } else {
+        encode_instructions(&mut payload, instructions);
+    }
+
+    // only present when signed, transactions without a refund keep their hash
+    if let Some(refund) = refund {
+        refund.encode(&mut payload);
+    }
+
+    Ok(keccak::hash(&payload).to_bytes())
+}
+
+/// Hash the owners sign to burn `nonce` without executing anything.
+pub fn create_cancel_hash(
+    domain: &TxHashDomain,
+    multisig_pda: Pubkey,
+    nonce: u64,
+) -> Result<[u8; 32], CommonError> {
+    validate_version(domain.version)?;
+
+    let mut payload = Vec::new();
+
+    payload.extend_from_slice(CANCEL_TAG);
+    payload.push(domain.version);
+    payload.extend_from_slice(&domain.program_id.to_bytes());
+    payload.extend_from_slice(&domain.chain_id);
+
+    payload.extend_from_slice(&multisig_pda.to_bytes());
+
+    payload.extend_from_slice(&nonce.to_le_bytes());
+
+    Ok(keccak::hash(&payload).to_bytes())
+}
+
+fn encode_instruction_v1(payload: &mut Vec<u8>, instruction: &MultiSigInstruction) {
+    for account in instruction.accounts.iter() {
+        payload.extend_from_slice(&account.pubkey.to_bytes());
+        payload.push(account.is_signer as u8);
+        payload.push(account.is_writable as u8);
+    }
+
+    payload.extend_from_slice(&instruction.program_id.to_bytes());
+
+    payload.extend_from_slice(&instruction.data);
+}
+
+// instructions are appended in execution order, `execute` hashes as a batch of one, the
+// counts and lengths make the boundaries between instructions, accounts and data unambiguous
+fn encode_instructions(payload: &mut Vec<u8>, instructions: &[MultiSigInstruction]) {
+    payload.extend_from_slice(&(instructions.len() as u32).to_le_bytes());
+    for instruction in instructions.iter() {
+        payload.extend_from_slice(&instruction.program_id.to_bytes());
+
+        payload.extend_from_slice(&(instruction.accounts.len() as u32).to_le_bytes());
+        for account in instruction.accounts.iter() {
+            payload.extend_from_slice(&account.pubkey.to_bytes());
+            payload.push(account.is_signer as u8);
+            payload.push(account.is_writable as u8);
+        }
+
+        payload.extend_from_slice(&(instruction.data.len() as u32).to_le_bytes());
+        payload.extend_from_slice(&instruction.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionAccount;
+
+    const KEY: Pubkey = Pubkey::new_from_array([7; 32]);
+    const TARGET: Pubkey = Pubkey::new_from_array([8; 32]);
+    const OTHER_TARGET: Pubkey = Pubkey::new_from_array([9; 32]);
+
+    fn hash(version: u8, instructions: &[MultiSigInstruction]) -> [u8; 32] {
+        hash_with_refund(version, instructions, None).unwrap()
+    }
+
+    fn hash_with_refund(
+        version: u8,
+        instructions: &[MultiSigInstruction],
+        refund: Option<&Refund>,
+    ) -> Result<[u8; 32], CommonError> {
+        let domain = TxHashDomain {
+            program_id: Pubkey::new_from_array([1; 32]),
+            chain_id: [2; 32],
+            version,
+        };
+        create_multi_sig_tx_hash(
+            &domain,
+            Pubkey::new_from_array([3; 32]),
+            5,
+            0,
+            i64::MAX,
+            instructions,
+            refund,
+        )
+    }
+
+    // one account and a target vs. no accounts, the account key as target and the rest as data
+    fn account_boundary_pair() -> (Vec<MultiSigInstruction>, Vec<MultiSigInstruction>) {
+        let with_account = vec![MultiSigInstruction {
+            program_id: TARGET,
+            accounts: vec![TransactionAccount {
+                pubkey: KEY,
+                is_signer: true,
+                is_writable: false,
+            }],
+            data: vec![0xaa, 0xbb],
+        }];
+
+        let mut data = vec![1, 0];
+        data.extend_from_slice(&TARGET.to_bytes());
+        data.extend_from_slice(&[0xaa, 0xbb]);
+        let without_account = vec![MultiSigInstruction {
+            program_id: KEY,
+            accounts: vec![],
+            data,
+        }];
+
+        (with_account, without_account)
+    }
+
+    // two instructions vs. the first one carrying the second in its data
+    fn instruction_boundary_pair() -> (Vec<MultiSigInstruction>, Vec<MultiSigInstruction>) {
+        let split = vec![
+            MultiSigInstruction {
+                program_id: TARGET,
+                accounts: vec![],
+                data: vec![0xaa],
+            },
+            MultiSigInstruction {
+                program_id: OTHER_TARGET,
+                accounts: vec![],
+                data: vec![0xbb],
+            },
+        ];
+
+        let mut data = vec![0xaa];
+        data.extend_from_slice(&OTHER_TARGET.to_bytes());
+        data.push(0xbb);
+        let merged = vec![MultiSigInstruction {
+            program_id: TARGET,
+            accounts: vec![],
+            data,
+        }];
+
+        (split, merged)
+    }
+
+    #[test]
+    fn v1_collides_on_field_boundaries() {
+        let (a, b) = account_boundary_pair();
+        assert_eq!(hash(TX_HASH_V1, &a), hash(TX_HASH_V1, &b));
+    }
+
+    #[test]
+    fn v1_rejects_batches_and_refunds() {
+        let (split, _) = instruction_boundary_pair();
+        assert_eq!(
+            hash_with_refund(TX_HASH_V1, &split, None),
+            Err(CommonError::InvalidHashVersion)
+        );
+        assert_eq!(
+            hash_with_refund(TX_HASH_V1, &[], None),
+            Err(CommonError::InvalidHashVersion)
+        );
+
+        let (a, _) = account_boundary_pair();
+        let refund = Refund {
+            amount: 5000,
+            mint: Pubkey::default(),
+            receiver: KEY,
+        };
+        assert_eq!(
+            hash_with_refund(TX_HASH_V1, &a, Some(&refund)),
+            Err(CommonError::InvalidRefund)
+        );
+    }
+
+    #[test]
+    fn versions_do_not_share_hashes() {
+        let (a, _) = account_boundary_pair();
+        assert_ne!(hash(TX_HASH_V1, &a), hash(TX_HASH_V2, &a));
+    }
+
+    #[test]
+    fn v2_separates_accounts_from_program() {
+        let (a, b) = account_boundary_pair();
+        assert_ne!(hash(TX_HASH_V2, &a), hash(TX_HASH_V2, &b));
+    }
+
+    #[test]
+    fn v2_separates_instructions() {
+        let (a, b) = instruction_boundary_pair();
+        assert_ne!(hash(TX_HASH_V2, &a), hash(TX_HASH_V2, &b));
+    }
+
+    // signatures for a batch must not verify for a single instruction carrying the rest of the
+    // batch in its data, or the other way around, in any layout a config can select
+    #[test]
+    fn batch_differs_from_one_instruction_carrying_it() {
+        let (split, _) = instruction_boundary_pair();
+
+        let mut data = split[0].data.clone();
+        encode_instructions(&mut data, &split[1..]);
+        let merged = vec![MultiSigInstruction {
+            program_id: split[0].program_id,
+            accounts: vec![],
+            data,
+        }];
+
+        for version in (0..=u8::MAX).filter(|version| validate_version(*version).is_ok()) {
+            let hash = |instructions| hash_with_refund(version, instructions, None);
+            assert_ne!(hash(&split), hash(&merged));
+            assert_ne!(hash(&split), hash(&split[..1]));
+        }
+    }
+
+    // pinned so client side encoders can be checked against the program
+    #[test]
+    fn pinned_vectors() {
+        let to_hex = |hash: [u8; 32]| -> String {
+            hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+        };
+        let (a, _) = account_boundary_pair();
+
+        assert_eq!(
+            to_hex(hash(TX_HASH_V1, &a)),
+            "cf66c1ff7270f16e1b9d00094512e1f37ad8e1ecafc703af782312d30f2d1224"
+        );
+        assert_eq!(
+            to_hex(hash(TX_HASH_V2, &a)),
+            "bc7513653a6194454d0130570017c7361686afefb3633ede4d7738172ac5b674"
+        );
+    }
+
+    #[test]
+    fn cancel_differs_from_an_empty_batch() {
+        let domain = TxHashDomain {
+            program_id: Pubkey::new_from_array([1; 32]),
+            chain_id: [2; 32],
+            version: TX_HASH_V2,
+        };
+        let pda = Pubkey::new_from_array([3; 32]);
+        let cancel = create_cancel_hash(&domain, pda, 5).unwrap();
+
+        assert_ne!(cancel, hash(TX_HASH_V2, &[]));
+        assert_ne!(cancel, create_cancel_hash(&domain, pda, 6).unwrap());
+        assert_eq!(
+            cancel
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>(),
+            "7f57be21f354472824be98733625226e5f057f0e7c531a4478bcc33f9bdb7290"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        for version in [0, 3] {
+            let domain = TxHashDomain {
+                program_id: Pubkey::default(),
+                chain_id: [0; 32],
+                version,
+            };
+            assert!(
+                create_multi_sig_tx_hash(&domain, Pubkey::default(), 0, 0, 0, &[], None).is_err()
+            );
+            assert!(create_cancel_hash(&domain, Pubkey::default(), 0).is_err());
+        }
+    }
+
+    #[test]
+    fn refund_is_signed() {
+        let (a, _) = account_boundary_pair();
+        let refund = Refund {
+            amount: 5000,
+            mint: Pubkey::default(),
+            receiver: KEY,
+        };
+        let with_refund = hash_with_refund(TX_HASH_V2, &a, Some(&refund)).unwrap();
+
+        assert_ne!(with_refund, hash(TX_HASH_V2, &a));
+        let other_receiver = Refund {
+            receiver: TARGET,
+            ..refund.clone()
+        };
+        assert_ne!(
+            with_refund,
+            hash_with_refund(TX_HASH_V2, &a, Some(&other_receiver)).unwrap()
+        );
+        assert_eq!(
+            with_refund
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>(),
+            "ccc0799bc4f2ef6eecdb4f03b3ad98ef793ce1a07ebe47aec259712853fa863b"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! ref to: https://docs.anza.xyz/runtime/programs#ed25519-program
+
+use crate::{parse_header, read_u16, ParseError, SignatureOffsets};
+
+pub const SIGNATURE_LEN: usize = 64;
+pub const PUBKEY_LEN: usize = 32;
+// signatures count + padding
+pub const OFFSETS_START: usize = 2;
+pub const OFFSETS_SIZE: usize = 14;
+/// instruction index used by the precompile to refer to its own data
+pub const CURRENT_INSTRUCTION_INDEX: u16 = u16::MAX;
+
+/// Decodes the `Ed25519SignatureOffsets` records of an ed25519 precompile instruction.
+pub fn parse(data: &[u8], expected_count: usize) -> Result<Vec<SignatureOffsets>, ParseError> {
+    let count = parse_header(data, OFFSETS_START, OFFSETS_SIZE, expected_count)?;
+
+    (0..count)
+        .map(|i| {
+            let base = OFFSETS_START + i * OFFSETS_SIZE;
+            Ok(SignatureOffsets {
+                signature_offset: read_u16(data, base)?,
+                signature_instruction_index: read_u16(data, base + 2)?,
+                public_key_offset: read_u16(data, base + 4)?,
+                public_key_instruction_index: read_u16(data, base + 6)?,
+                message_data_offset: read_u16(data, base + 8)?,
+                message_data_size: read_u16(data, base + 10)?,
+                message_instruction_index: read_u16(data, base + 12)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_bytes;
+    use proptest::prelude::*;
+
+    // same layout as `BatchEd25519Signer.createVerifySignaturesInstruction`
+    fn encode(entries: &[([u8; PUBKEY_LEN], Vec<u8>)]) -> Vec<u8> {
+        let header_size = OFFSETS_START + entries.len() * OFFSETS_SIZE;
+        let pubkeys_start = header_size + entries.len() * SIGNATURE_LEN;
+        let mut message_offset = pubkeys_start + entries.len() * PUBKEY_LEN;
+
+        let mut data = vec![entries.len() as u8, 0];
+        let mut body = vec![0u8; entries.len() * SIGNATURE_LEN];
+        let mut messages = Vec::new();
+        for (i, (pubkey, message)) in entries.iter().enumerate() {
+            for value in [
+                (header_size + i * SIGNATURE_LEN) as u16,
+                CURRENT_INSTRUCTION_INDEX,
+                (pubkeys_start + i * PUBKEY_LEN) as u16,
+                CURRENT_INSTRUCTION_INDEX,
+                message_offset as u16,
+                message.len() as u16,
+                CURRENT_INSTRUCTION_INDEX,
+            ] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            body.extend_from_slice(pubkey);
+            messages.extend_from_slice(message);
+            message_offset += message.len();
+        }
+        data.extend_from_slice(&body);
+        data.extend_from_slice(&messages);
+        data
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        assert_eq!(parse(&[], 0), Err(ParseError::TruncatedHeader));
+        assert_eq!(parse(&[1], 1), Err(ParseError::TruncatedHeader));
+        assert_eq!(parse(&[2, 0, 0, 0], 2), Err(ParseError::TruncatedHeader));
+    }
+
+    #[test]
+    fn rejects_count_mismatch() {
+        let data = encode(&[([7; PUBKEY_LEN], vec![1; 32])]);
+        assert_eq!(parse(&data, 2), Err(ParseError::CountMismatch));
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics(data in proptest::collection::vec(any::<u8>(), 0..512), expected_count in 0usize..8) {
+            if let Ok(records) = parse(&data, expected_count) {
+                prop_assert_eq!(records.len(), expected_count);
+                for record in records {
+                    let _ = read_bytes(&data, record.public_key_offset as usize, PUBKEY_LEN);
+                    let _ = read_bytes(&data, record.message_data_offset as usize, record.message_data_size as usize);
+                }
+            }
+        }
+
+        #[test]
+        fn decodes_well_formed_data(entries in proptest::collection::vec((any::<[u8; PUBKEY_LEN]>(), proptest::collection::vec(any::<u8>(), 0..64)), 0..6)) {
+            let data = encode(&entries);
+            let records = parse(&data, entries.len()).unwrap();
+            for (record, (pubkey, message)) in records.iter().zip(entries.iter()) {
+                prop_assert_eq!(read_bytes(&data, record.public_key_offset as usize, PUBKEY_LEN).unwrap(), &pubkey[..]);
+                prop_assert_eq!(read_bytes(&data, record.message_data_offset as usize, record.message_data_size as usize).unwrap(), &message[..]);
+                prop_assert_eq!(record.message_instruction_index, CURRENT_INSTRUCTION_INDEX);
+            }
+        }
+
+        #[test]
+        fn truncated_well_formed_data_never_panics(entries in proptest::collection::vec((any::<[u8; PUBKEY_LEN]>(), proptest::collection::vec(any::<u8>(), 0..64)), 1..6), cut in any::<proptest::sample::Index>()) {
+            let data = encode(&entries);
+            let truncated = &data[..cut.index(data.len())];
+            let header_size = OFFSETS_START + entries.len() * OFFSETS_SIZE;
+            match parse(truncated, entries.len()) {
+                Ok(_) => prop_assert!(truncated.len() >= header_size),
+                Err(err) => {
+                    prop_assert_eq!(err, ParseError::TruncatedHeader);
+                    prop_assert!(truncated.len() < header_size);
+                }
+            }
+        }
+    }
+}
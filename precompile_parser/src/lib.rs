@@ -0,0 +1,88 @@
+//! Shared parsing of the signature verification precompile instruction data.
+//!
+//! Both precompiles encode `[count][padding?][offsets * count][data]`, this crate decodes the
+//! offsets records and hands out slices of the instruction data without ever indexing out of
+//! bounds, so a malformed instruction surfaces as a [`ParseError`] instead of a panic.
+
+pub mod ed25519;
+pub mod secp256k1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// the data is too short to hold the count byte and every offsets record
+    TruncatedHeader,
+    /// an offset + length pair points outside the instruction data
+    OffsetOutOfRange,
+    /// the number of signatures differs from the number of expected signers
+    CountMismatch,
+}
+
+/// A decoded offsets record, instruction indices are widened to `u16` for both precompiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureOffsets {
+    pub signature_offset: u16,
+    pub signature_instruction_index: u16,
+    pub public_key_offset: u16,
+    pub public_key_instruction_index: u16,
+    pub message_data_offset: u16,
+    pub message_data_size: u16,
+    pub message_instruction_index: u16,
+}
+
+/// Returns `data[offset..offset + len]` or [`ParseError::OffsetOutOfRange`].
+pub fn read_bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8], ParseError> {
+    let end = offset
+        .checked_add(len)
+        .ok_or(ParseError::OffsetOutOfRange)?;
+    data.get(offset..end).ok_or(ParseError::OffsetOutOfRange)
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, ParseError> {
+    data.get(offset).copied().ok_or(ParseError::TruncatedHeader)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ParseError> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .ok_or(ParseError::TruncatedHeader)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+/// Reads the signature count and makes sure every offsets record fits in `data`.
+fn parse_header(
+    data: &[u8],
+    offsets_start: usize,
+    offsets_size: usize,
+    expected_count: usize,
+) -> Result<usize, ParseError> {
+    if data.len() < offsets_start {
+        return Err(ParseError::TruncatedHeader);
+    }
+
+    let count = read_u8(data, 0)? as usize;
+    if count != expected_count {
+        return Err(ParseError::CountMismatch);
+    }
+
+    if data.len() < offsets_start + count * offsets_size {
+        return Err(ParseError::TruncatedHeader);
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn read_bytes_never_panics(data in proptest::collection::vec(any::<u8>(), 0..256), offset in any::<usize>(), len in any::<usize>()) {
+            match read_bytes(&data, offset, len) {
+                Ok(slice) => prop_assert_eq!(slice, &data[offset..offset + len]),
+                Err(err) => prop_assert_eq!(err, ParseError::OffsetOutOfRange),
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! ref to: https://docs.anza.xyz/runtime/programs#secp256k1-program
+
+use crate::{parse_header, read_u16, read_u8, ParseError, SignatureOffsets};
+
+pub const SIGNATURE_LEN: usize = 64;
+pub const RECOVERY_ID_LEN: usize = 1;
+pub const ETH_ADDRESS_LEN: usize = 20;
+// signatures count
+pub const OFFSETS_START: usize = 1;
+pub const OFFSETS_SIZE: usize = 11;
+
+/// Decodes the `Secp256k1SignatureOffsets` records of a secp256k1 precompile instruction.
+pub fn parse(data: &[u8], expected_count: usize) -> Result<Vec<SignatureOffsets>, ParseError> {
+    let count = parse_header(data, OFFSETS_START, OFFSETS_SIZE, expected_count)?;
+
+    (0..count)
+        .map(|i| {
+            let base = OFFSETS_START + i * OFFSETS_SIZE;
+            Ok(SignatureOffsets {
+                signature_offset: read_u16(data, base)?,
+                signature_instruction_index: read_u8(data, base + 2)? as u16,
+                public_key_offset: read_u16(data, base + 3)?,
+                public_key_instruction_index: read_u8(data, base + 5)? as u16,
+                message_data_offset: read_u16(data, base + 6)?,
+                message_data_size: read_u16(data, base + 8)?,
+                message_instruction_index: read_u8(data, base + 10)? as u16,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_bytes;
+    use proptest::prelude::*;
+
+    // same layout as `BatchSecp256k1Signer.createVerifySignaturesInstruction`
+    fn encode(entries: &[([u8; ETH_ADDRESS_LEN], Vec<u8>)], ix_index: u8) -> Vec<u8> {
+        let data_start = OFFSETS_START + entries.len() * OFFSETS_SIZE;
+        let mut address_offset = data_start;
+        let mut signature_offset = address_offset + entries.len() * ETH_ADDRESS_LEN;
+        let mut message_offset =
+            signature_offset + entries.len() * (SIGNATURE_LEN + RECOVERY_ID_LEN);
+
+        let mut data = vec![entries.len() as u8];
+        let mut addresses = Vec::new();
+        let mut messages = Vec::new();
+        for (address, message) in entries.iter() {
+            data.extend_from_slice(&(signature_offset as u16).to_le_bytes());
+            data.push(ix_index);
+            data.extend_from_slice(&(address_offset as u16).to_le_bytes());
+            data.push(ix_index);
+            data.extend_from_slice(&(message_offset as u16).to_le_bytes());
+            data.extend_from_slice(&(message.len() as u16).to_le_bytes());
+            data.push(ix_index);
+
+            addresses.extend_from_slice(address);
+            messages.extend_from_slice(message);
+            address_offset += ETH_ADDRESS_LEN;
+            signature_offset += SIGNATURE_LEN + RECOVERY_ID_LEN;
+            message_offset += message.len();
+        }
+        data.extend_from_slice(&addresses);
+        data.resize(
+            data.len() + entries.len() * (SIGNATURE_LEN + RECOVERY_ID_LEN),
+            0,
+        );
+        data.extend_from_slice(&messages);
+        data
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        assert_eq!(parse(&[], 0), Err(ParseError::TruncatedHeader));
+        assert_eq!(parse(&[1, 0, 0], 1), Err(ParseError::TruncatedHeader));
+    }
+
+    #[test]
+    fn rejects_count_mismatch() {
+        let data = encode(&[([7; ETH_ADDRESS_LEN], vec![1; 32])], 0);
+        assert_eq!(parse(&data, 0), Err(ParseError::CountMismatch));
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics(data in proptest::collection::vec(any::<u8>(), 0..512), expected_count in 0usize..8) {
+            if let Ok(records) = parse(&data, expected_count) {
+                prop_assert_eq!(records.len(), expected_count);
+                for record in records {
+                    let _ = read_bytes(&data, record.public_key_offset as usize, ETH_ADDRESS_LEN);
+                    let _ = read_bytes(&data, record.message_data_offset as usize, record.message_data_size as usize);
+                }
+            }
+        }
+
+        #[test]
+        fn decodes_well_formed_data(entries in proptest::collection::vec((any::<[u8; ETH_ADDRESS_LEN]>(), proptest::collection::vec(any::<u8>(), 0..64)), 0..6), ix_index in any::<u8>()) {
+            let data = encode(&entries, ix_index);
+            let records = parse(&data, entries.len()).unwrap();
+            for (record, (address, message)) in records.iter().zip(entries.iter()) {
+                prop_assert_eq!(read_bytes(&data, record.public_key_offset as usize, ETH_ADDRESS_LEN).unwrap(), &address[..]);
+                prop_assert_eq!(read_bytes(&data, record.message_data_offset as usize, record.message_data_size as usize).unwrap(), &message[..]);
+                prop_assert_eq!(record.message_instruction_index, ix_index as u16);
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::keccak;
+
+use crate::refund::Refund;
+use crate::safe_tx::address_word;
+use crate::signing::SignedTx;
+use crate::tx_hash::TxHashDomain;
+use crate::{MultiSigInstruction, TransactionAccount};
+
+pub const DOMAIN_NAME: &str = "Stateless Eth Multisig";
+pub const DOMAIN_VERSION: &str = "1";
+
+// `verifyingContract` is limited to 20 byte addresses, so it holds the ethereum style address
+// of the program id and the cluster's 32 byte chain id rides in `salt`
+pub const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract,bytes32 salt)";
+pub const ACCOUNT_META_TYPE: &str = "AccountMeta(bytes32 pubkey,bool isSigner,bool isWritable)";
+pub const INSTRUCTION_TYPE: &str = "Instruction(bytes32 programId,AccountMeta[] accounts,bytes data)AccountMeta(bytes32 pubkey,bool isSigner,bool isWritable)";
+pub const TX_TYPE: &str = "SolanaMultisigTx(bytes32 multisigPda,uint64 nonce,int64 validAfter,int64 validUntil,Instruction[] instructions)AccountMeta(bytes32 pubkey,bool isSigner,bool isWritable)Instruction(bytes32 programId,AccountMeta[] accounts,bytes data)";
+pub const REFUND_TYPE: &str = "Refund(uint64 amount,bytes32 mint,bytes32 receiver)";
+pub const REFUND_TX_TYPE: &str = "SolanaMultisigRefundTx(bytes32 multisigPda,uint64 nonce,int64 validAfter,int64 validUntil,Instruction[] instructions,Refund refund)AccountMeta(bytes32 pubkey,bool isSigner,bool isWritable)Instruction(bytes32 programId,AccountMeta[] accounts,bytes data)Refund(uint64 amount,bytes32 mint,bytes32 receiver)";
+pub const CANCEL_TYPE: &str = "SolanaMultisigCancel(bytes32 multisigPda,uint64 nonce)";
+
+/// Returns `0x19 0x01 || domainSeparator || hashStruct(tx)`, the precompile keccak hashes it
+/// into the EIP-712 digest the wallet signed.
+pub fn typed_data_message(tx: &SignedTx) -> Vec<u8> {
+    encode(&domain_separator(tx.domain, tx.evm_chain_id), &hash_tx(tx))
+}
+
+/// Typed data message burning `nonce`, signed in the same domain as transactions.
+pub fn cancel_message(
+    domain: &TxHashDomain,
+    evm_chain_id: u64,
+    multisig_pda: Pubkey,
+    nonce: u64,
+) -> Vec<u8> {
+    encode(
+        &domain_separator(domain, evm_chain_id),
+        &hash(&[
+            &hash(&[CANCEL_TYPE.as_bytes()]),
+            &multisig_pda.to_bytes(),
+            &u64_word(nonce),
+        ]),
+    )
+}
+
+pub(crate) fn encode(domain_separator: &[u8; 32], struct_hash: &[u8; 32]) -> Vec<u8> {
+    [&[0x19, 0x01][..], domain_separator, struct_hash].concat()
+}
+
+// wallets only sign typed data for the chain they are connected to, so `chainId` is the
+// config's EVM chain id
+fn domain_separator(domain: &TxHashDomain, evm_chain_id: u64) -> [u8; 32] {
+    hash(&[
+        &hash(&[DOMAIN_TYPE.as_bytes()]),
+        &hash(&[DOMAIN_NAME.as_bytes()]),
+        &hash(&[DOMAIN_VERSION.as_bytes()]),
+        &u64_word(evm_chain_id),
+        &address_word(&domain.program_id),
+        &domain.chain_id,
+    ])
+}
+
+fn hash_tx(tx: &SignedTx) -> [u8; 32] {
+    let instructions: Vec<[u8; 32]> = tx.instructions.iter().map(hash_instruction).collect();
+    // a transaction with a refund is its own type, approvals without one keep their digest
+    let tx_type = match tx.refund {
+        Some(_) => REFUND_TX_TYPE,
+        None => TX_TYPE,
+    };
+    let refund = tx.refund.map(hash_refund);
+
+    hash(&[
+        &hash(&[tx_type.as_bytes()]),
+        &tx.multisig_pda.to_bytes(),
+        &u64_word(tx.nonce),
+        &i64_word(tx.valid_after),
+        &i64_word(tx.valid_until),
+        &hash_array(&instructions),
+        refund.as_ref().map_or(&[], |refund| &refund[..]),
+    ])
+}
+
+fn hash_refund(refund: &Refund) -> [u8; 32] {
+    hash(&[
+        &hash(&[REFUND_TYPE.as_bytes()]),
+        &u64_word(refund.amount),
+        &refund.mint.to_bytes(),
+        &refund.receiver.to_bytes(),
+    ])
+}
+
+fn hash_instruction(instruction: &MultiSigInstruction) -> [u8; 32] {
+    let accounts: Vec<[u8; 32]> = instruction.accounts.iter().map(hash_account_meta).collect();
+
+    hash(&[
+        &hash(&[INSTRUCTION_TYPE.as_bytes()]),
+        &instruction.program_id.to_bytes(),
+        &hash_array(&accounts),
+        &hash(&[&instruction.data]),
+    ])
+}
+
+fn hash_account_meta(account: &TransactionAccount) -> [u8; 32] {
+    hash(&[
+        &hash(&[ACCOUNT_META_TYPE.as_bytes()]),
+        &account.pubkey.to_bytes(),
+        &bool_word(account.is_signer),
+        &bool_word(account.is_writable),
+    ])
+}
+
+// arrays of structs are encoded as the hash of their concatenated struct hashes
+fn hash_array(items: &[[u8; 32]]) -> [u8; 32] {
+    hash(&[&items.concat()])
+}
+
+pub(crate) fn hash(parts: &[&[u8]]) -> [u8; 32] {
+    keccak::hashv(parts).to_bytes()
+}
+
+pub(crate) fn u64_word(value: u64) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+// signed integers are sign extended to 256 bits
+fn i64_word(value: i64) -> [u8; 32] {
+    let mut word = if value < 0 { [0xff; 32] } else { [0; 32] };
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn bool_word(value: bool) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[31] = value as u8;
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx_hash::{TxHashDomain, TX_HASH_V2};
+    use anchor_lang::prelude::Pubkey;
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(u64_word(5)[31], 5);
+        assert_eq!(i64_word(-1), [0xff; 32]);
+        assert_eq!(i64_word(i64::MAX)[..24], [0; 24]);
+        assert_eq!(bool_word(true)[31], 1);
+    }
+
+    // pinned against an independent EIP-712 encoder, the same vector is checked with ethers
+    #[test]
+    fn pinned_vector() {
+        let domain = TxHashDomain {
+            program_id: Pubkey::new_from_array([1; 32]),
+            chain_id: [2; 32],
+            version: TX_HASH_V2,
+        };
+        let instructions = [MultiSigInstruction {
+            program_id: Pubkey::new_from_array([8; 32]),
+            accounts: vec![TransactionAccount {
+                pubkey: Pubkey::new_from_array([7; 32]),
+                is_signer: true,
+                is_writable: false,
+            }],
+            data: vec![0xaa, 0xbb],
+        }];
+        let tx = SignedTx {
+            domain: &domain,
+            evm_chain_id: 1,
+            multisig_pda: Pubkey::new_from_array([3; 32]),
+            nonce: 5,
+            valid_after: 0,
+            valid_until: i64::MAX,
+            instructions: &instructions,
+            refund: None,
+        };
+
+        let message = typed_data_message(&tx);
+        assert_eq!(message.len(), 66);
+        assert_eq!(
+            to_hex(&message[2..34]),
+            "9493e3476f3f0977cc91c4fefdb430a2b6cbc868d879eb5a6e36439e3aa1183e"
+        );
+        assert_eq!(
+            to_hex(&keccak::hash(&message).to_bytes()),
+            "e002423f7ea13f3e6fbd3adb320423702df9e43c01868de876cb6a399ee6edd4"
+        );
+
+        let cancel = cancel_message(&domain, tx.evm_chain_id, tx.multisig_pda, tx.nonce);
+        assert_eq!(
+            to_hex(&keccak::hash(&cancel).to_bytes()),
+            "4bbccf82cd421f5972e75ce6bd52b657a3c018a1dd44163df534af909e56403b"
+        );
+
+        let refund = Refund {
+            amount: 5000,
+            mint: Pubkey::default(),
+            receiver: Pubkey::new_from_array([7; 32]),
+        };
+        let with_refund = typed_data_message(&SignedTx {
+            refund: Some(&refund),
+            ..tx
+        });
+        assert_eq!(with_refund[2..34], message[2..34]);
+        assert_eq!(
+            to_hex(&keccak::hash(&with_refund).to_bytes()),
+            "e54e03b3710ca22214b26da08d61c5933d4ad26a6a31cd536277b12447511345"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use anchor_lang::event::EVENT_IX_TAG_LE;
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+use crate::refund::Refund;
+
+// emitted through `emit_cpi!`, the event is the data of a self invocation and is not lost
+// when the logs of a transaction get truncated
+
+#[event]
+pub struct MultisigCreated {
+    pub config: Pubkey,
+    pub multisig_pda: Pubkey,
+    pub owners: Vec<[u8; 20]>,
+    pub weights: Vec<u8>,
+    pub threshold: u8,
+    pub chain_id: [u8; 32],
+    pub evm_chain_id: u64,
+    pub hash_version: u8,
+}
+
+#[event]
+pub struct TransactionExecuted {
+    pub config: Pubkey,
+    pub tx_hash: [u8; 32],
+    pub nonce: u64,
+    pub vault_index: u8,
+    pub signers: Vec<[u8; 20]>,
+    pub programs: Vec<Pubkey>, // target program of each instruction, in execution order
+    pub refund: Option<Refund>,
+}
+
+/// An enabled module executed instructions through the multisig pda, without approvals.
+#[event]
+pub struct ModuleTransactionExecuted {
+    pub config: Pubkey,
+    pub module: Pubkey,
+    pub instruction_count: u32,
+}
+
+/// A pending nonce was burned by `cancel`, proposals signed for it can no longer execute.
+#[event]
+pub struct NonceCancelled {
+    pub config: Pubkey,
+    pub vault_index: u8,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct OwnerAdded {
+    pub config: Pubkey,
+    pub owner: [u8; 20],
+    pub weight: u8,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct OwnerRemoved {
+    pub config: Pubkey,
+    pub owner: [u8; 20],
+    pub threshold: u8,
+}
+
+#[event]
+pub struct OwnerSwapped {
+    pub config: Pubkey,
+    pub old_owner: [u8; 20],
+    pub new_owner: [u8; 20],
+}
+
+#[event]
+pub struct WeightChanged {
+    pub config: Pubkey,
+    pub owner: [u8; 20],
+    pub weight: u8,
+}
+
+#[event]
+pub struct ThresholdChanged {
+    pub config: Pubkey,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct HashVersionChanged {
+    pub config: Pubkey,
+    pub hash_version: u8,
+}
+
+#[event]
+pub struct GuardChanged {
+    pub config: Pubkey,
+    pub guard: Option<Pubkey>,
+}
+
+#[event]
+pub struct ModuleEnabled {
+    pub config: Pubkey,
+    pub module: Pubkey,
+}
+
+#[event]
+pub struct ModuleDisabled {
+    pub config: Pubkey,
+    pub module: Pubkey,
+}
+
+#[event]
+pub struct VaultChanged {
+    pub config: Pubkey,
+    pub index: u8,
+    pub owners: Vec<[u8; 20]>,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct VaultRemoved {
+    pub config: Pubkey,
+    pub index: u8,
+}
+
+#[event]
+pub struct MultisigClosed {
+    pub config: Pubkey,
+    pub receiver: Pubkey,
+}
+
+#[event]
+pub struct RolesChanged {
+    pub config: Pubkey,
+    pub owner: [u8; 20],
+    pub roles: u8,
+}
+
+#[event]
+pub struct ExecutorsChanged {
+    pub config: Pubkey,
+    pub executors: Vec<Pubkey>,
+}
+
+#[event]
+pub struct PolicyAdded {
+    pub config: Pubkey,
+    pub program_id: Pubkey,
+    pub prefix: Vec<u8>,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct PolicyRemoved {
+    pub config: Pubkey,
+    pub program_id: Pubkey,
+    pub prefix: Vec<u8>,
+}
+
+#[event]
+pub struct AdminThresholdChanged {
+    pub config: Pubkey,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct AllowanceSet {
+    pub config: Pubkey,
+    pub delegate: Pubkey,
+    pub mint: Pubkey, // Pubkey::default() for lamports
+    pub amount: u64,
+    pub period: i64,
+}
+
+#[event]
+pub struct AllowanceRemoved {
+    pub config: Pubkey,
+    pub delegate: Pubkey,
+    pub mint: Pubkey,
+}
+
+/// A delegate spent from its allowance, without approvals.
+#[event]
+pub struct AllowanceSpent {
+    pub config: Pubkey,
+    pub delegate: Pubkey,
+    pub mint: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+}
+
+macro_rules! multisig_events {
+    ($($event:ident),* $(,)?) => {
+        /// Any event of the program.
+        pub enum MultisigEvent {
+            $($event($event),)*
+        }
+
+        impl MultisigEvent {
+            /// Decodes the data of an inner instruction made by `emit_cpi!`. Returns `None` for
+            /// data that is not an event, callers check the instruction targets this program.
+            pub fn decode(data: &[u8]) -> Option<Self> {
+                let data = data.strip_prefix(&EVENT_IX_TAG_LE[..])?;
+                let discriminator = data.get(..8)?;
+                $(
+                    if discriminator == $event::DISCRIMINATOR {
+                        return $event::try_from_slice(&data[8..]).ok().map(Self::$event);
+                    }
+                )*
+                None
+            }
+        }
+    };
+}
+
+multisig_events!(
+    MultisigCreated,
+    MultisigClosed,
+    TransactionExecuted,
+    ModuleTransactionExecuted,
+    NonceCancelled,
+    OwnerAdded,
+    OwnerRemoved,
+    OwnerSwapped,
+    WeightChanged,
+    ThresholdChanged,
+    HashVersionChanged,
+    GuardChanged,
+    ModuleEnabled,
+    ModuleDisabled,
+    VaultChanged,
+    VaultRemoved,
+    RolesChanged,
+    PolicyAdded,
+    PolicyRemoved,
+    AdminThresholdChanged,
+    ExecutorsChanged,
+    AllowanceSet,
+    AllowanceRemoved,
+    AllowanceSpent,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::Event;
+
+    // what `emit_cpi!` puts into the self invocation
+    fn emitted(event: &impl Event) -> Vec<u8> {
+        [&EVENT_IX_TAG_LE[..], &event.data()].concat()
+    }
+
+    #[test]
+    fn decodes_emitted_events() {
+        let event = TransactionExecuted {
+            config: Pubkey::new_from_array([1; 32]),
+            tx_hash: [2; 32],
+            nonce: 5,
+            vault_index: 1,
+            signers: vec![[9; 20]],
+            programs: vec![Pubkey::new_from_array([8; 32])],
+            refund: Some(Refund {
+                amount: 5000,
+                mint: Pubkey::default(),
+                receiver: Pubkey::new_from_array([7; 32]),
+            }),
+        };
+
+        let Some(MultisigEvent::TransactionExecuted(decoded)) =
+            MultisigEvent::decode(&emitted(&event))
+        else {
+            panic!("expected a TransactionExecuted event");
+        };
+        assert_eq!(decoded.config, event.config);
+        assert_eq!(decoded.tx_hash, event.tx_hash);
+        assert_eq!(decoded.nonce, event.nonce);
+        assert_eq!(decoded.vault_index, event.vault_index);
+        assert!(decoded.signers == event.signers);
+        assert_eq!(decoded.programs, event.programs);
+        assert_eq!(
+            decoded.refund.map(|refund| refund.receiver),
+            Some(Pubkey::new_from_array([7; 32]))
+        );
+
+        let event = GuardChanged {
+            config: Pubkey::new_from_array([1; 32]),
+            guard: None,
+        };
+        assert!(matches!(
+            MultisigEvent::decode(&emitted(&event)),
+            Some(MultisigEvent::GuardChanged(GuardChanged {
+                guard: None,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn ignores_other_data() {
+        let event = ThresholdChanged {
+            config: Pubkey::new_from_array([1; 32]),
+            threshold: 2,
+        };
+        let data = emitted(&event);
+
+        // plain instruction data, a truncated event and an unknown discriminator
+        assert!(MultisigEvent::decode(&data[8..]).is_none());
+        assert!(MultisigEvent::decode(&data[..data.len() - 1]).is_none());
+        let mut unknown = data.clone();
+        unknown[8] ^= 0xff;
+        assert!(MultisigEvent::decode(&unknown).is_none());
+    }
+}
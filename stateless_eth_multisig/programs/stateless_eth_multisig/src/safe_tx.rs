@@ -0,0 +1,197 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak;
+
+use crate::eip712::{encode, hash, u64_word};
+use crate::errors::MultiSigErrors;
+use crate::refund::Refund;
+use crate::signing::SignedTx;
+
+// the domain and struct Safe (v1.3.0 and later) signs, field order matters for the hash
+pub const DOMAIN_TYPE: &str = "EIP712Domain(uint256 chainId,address verifyingContract)";
+pub const SAFE_TX_TYPE: &str = "SafeTx(address to,uint256 value,bytes data,uint8 operation,uint256 safeTxGas,uint256 baseGas,uint256 gasPrice,address gasToken,address refundReceiver,uint256 nonce)";
+
+/// Returns `0x19 0x01 || domainSeparator || hashStruct(SafeTx)` for a single instruction.
+///
+/// `to` is the address of the target program and `data` the borsh encoded instruction, which
+/// commits to the program id, accounts and data. The multisig pda is the verifying contract.
+/// SafeTx has no deadline, so `execute` only accepts these signatures with an open window.
+/// A refund goes into Safe's gas refund fields, see `refund_words`.
+pub fn safe_tx_message(tx: &SignedTx) -> Result<Vec<u8>> {
+    let [instruction] = tx.instructions else {
+        return err!(MultiSigErrors::UnsupportedSigningMode);
+    };
+    let data = instruction.try_to_vec()?;
+
+    Ok(encode(
+        &domain_separator(&tx.multisig_pda, tx.evm_chain_id),
+        &hash_safe_tx(&instruction.program_id, &data, tx.nonce, tx.refund),
+    ))
+}
+
+/// Safe's rejection transaction, an empty call from the multisig to itself at `nonce`.
+/// Executions always carry a borsh encoded instruction, so the empty data can not collide.
+pub fn rejection_message(evm_chain_id: u64, multisig_pda: Pubkey, nonce: u64) -> Vec<u8> {
+    encode(
+        &domain_separator(&multisig_pda, evm_chain_id),
+        &hash_safe_tx(&multisig_pda, &[], nonce, None),
+    )
+}
+
+fn hash_safe_tx(to: &Pubkey, data: &[u8], nonce: u64, refund: Option<&Refund>) -> [u8; 32] {
+    let [base_gas, gas_price, gas_token, refund_receiver] = refund_words(refund);
+    hash(&[
+        &hash(&[SAFE_TX_TYPE.as_bytes()]),
+        &address_word(to),
+        &[0; 32], // value
+        &hash(&[data]),
+        &[0; 32], // operation, always a call
+        &[0; 32], // safeTxGas
+        &base_gas,
+        &gas_price,
+        &gas_token,
+        &refund_receiver,
+        &u64_word(nonce),
+    ])
+}
+
+// Safe refunds `(gasUsed + baseGas) * gasPrice`, without gas metering the refund amount is the
+// base gas at a price of 1. SOL and the relayer are the zero address, like ether and `tx.origin`.
+fn refund_words(refund: Option<&Refund>) -> [[u8; 32]; 4] {
+    let Some(refund) = refund else {
+        return [[0; 32]; 4];
+    };
+    [
+        u64_word(refund.amount),
+        u64_word(1),
+        optional_address_word(&refund.mint),
+        optional_address_word(&refund.receiver),
+    ]
+}
+
+fn optional_address_word(key: &Pubkey) -> [u8; 32] {
+    if *key == Pubkey::default() {
+        return [0; 32];
+    }
+    address_word(key)
+}
+
+// Safe's domain has no room for the cluster, the multisig pda binds the program and config
+fn domain_separator(multisig_pda: &Pubkey, evm_chain_id: u64) -> [u8; 32] {
+    hash(&[
+        &hash(&[DOMAIN_TYPE.as_bytes()]),
+        &u64_word(evm_chain_id),
+        &address_word(multisig_pda),
+    ])
+}
+
+/// Ethereum style address of a solana key, the last 20 bytes of its keccak hash.
+pub fn eth_address(key: &Pubkey) -> [u8; 20] {
+    let mut address = [0; 20];
+    address.copy_from_slice(&keccak::hash(&key.to_bytes()).to_bytes()[12..]);
+    address
+}
+
+pub(crate) fn address_word(key: &Pubkey) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[12..].copy_from_slice(&eth_address(key));
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tx_hash::{TxHashDomain, TX_HASH_V2};
+    use crate::{MultiSigInstruction, TransactionAccount};
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn instruction() -> MultiSigInstruction {
+        MultiSigInstruction {
+            program_id: Pubkey::new_from_array([8; 32]),
+            accounts: vec![TransactionAccount {
+                pubkey: Pubkey::new_from_array([7; 32]),
+                is_signer: true,
+                is_writable: false,
+            }],
+            data: vec![0xaa, 0xbb],
+        }
+    }
+
+    fn message(instructions: &[MultiSigInstruction]) -> Result<Vec<u8>> {
+        message_with_refund(instructions, None)
+    }
+
+    fn message_with_refund(
+        instructions: &[MultiSigInstruction],
+        refund: Option<&Refund>,
+    ) -> Result<Vec<u8>> {
+        let domain = TxHashDomain {
+            program_id: Pubkey::new_from_array([1; 32]),
+            chain_id: [2; 32],
+            version: TX_HASH_V2,
+        };
+        safe_tx_message(&SignedTx {
+            domain: &domain,
+            evm_chain_id: 1,
+            multisig_pda: Pubkey::new_from_array([3; 32]),
+            nonce: 5,
+            valid_after: 0,
+            valid_until: i64::MAX,
+            instructions,
+            refund,
+        })
+    }
+
+    // pinned against an independent EIP-712 encoder, the same vector is checked with ethers
+    #[test]
+    fn pinned_vector() {
+        let message = message(&[instruction()]).unwrap();
+
+        assert_eq!(message.len(), 66);
+        assert_eq!(
+            to_hex(&eth_address(&Pubkey::new_from_array([3; 32]))),
+            "8e1003e6086df449b615bb411c39669548e19dba"
+        );
+        assert_eq!(
+            to_hex(&message[2..34]),
+            "a14f8d85a7158fe16b74c6d03b15de27ff62d1baa27704326275e1719ba28b69"
+        );
+        assert_eq!(
+            to_hex(&keccak::hash(&message).to_bytes()),
+            "a63b24aad2153204f26b847f7b009e2f554ebbe512af21b0b9e1c5fa98189d59"
+        );
+    }
+
+    #[test]
+    fn refund_vector() {
+        let refund = Refund {
+            amount: 5000,
+            mint: Pubkey::default(),
+            receiver: Pubkey::new_from_array([7; 32]),
+        };
+        let message = message_with_refund(&[instruction()], Some(&refund)).unwrap();
+
+        assert_eq!(
+            to_hex(&keccak::hash(&message).to_bytes()),
+            "ecb9f2b5f0db2750651a728ff2b661b51a764a1b673f715e6e59d1af7eeb9496"
+        );
+    }
+
+    #[test]
+    fn rejection_vector() {
+        let message = rejection_message(1, Pubkey::new_from_array([3; 32]), 5);
+
+        assert_eq!(
+            to_hex(&keccak::hash(&message).to_bytes()),
+            "e50923dbc433d09bf6ce162d9ccc623098e42305f0396a6bc3ace0f23fff9f1e"
+        );
+    }
+
+    #[test]
+    fn requires_a_single_instruction() {
+        assert!(message(&[]).is_err());
+        assert!(message(&[instruction(), instruction()]).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use anchor_lang::prelude::*;
+
+use crate::eip712;
+use crate::errors::MultiSigErrors;
+use crate::refund::Refund;
+use crate::safe_tx;
+use crate::tx_hash::{create_cancel_hash, create_multi_sig_tx_hash, TxHashDomain};
+use crate::MultiSigInstruction;
+
+/// How owners sign the transaction. The secp256k1 precompile keccak hashes its message
+/// before recovering the signer, so each mode maps to the preimage the wallet hashed.
+#[derive(AnchorDeserialize, AnchorSerialize, Clone, Copy, PartialEq, Eq)]
+pub enum SigningMode {
+    /// the precompile message is the transaction hash itself
+    Raw,
+    /// EIP-191 `personal_sign` over the 32 byte transaction hash
+    PersonalSign,
+    /// EIP-712 typed data, see `eip712`
+    Eip712,
+    /// Safe's `SafeTx` typed data over a single instruction, see `safe_tx`
+    SafeTx,
+}
+
+// EIP-191 version 0x45 prefix, the length is fixed since only 32 byte hashes are signed
+pub const PERSONAL_SIGN_PREFIX: &[u8] = b"\x19Ethereum Signed Message:\n32";
+
+/// Everything an approval commits to, independent of the signing mode.
+pub struct SignedTx<'a> {
+    pub domain: &'a TxHashDomain,
+    pub evm_chain_id: u64, // chain id of the typed data domains
+    pub multisig_pda: Pubkey,
+    pub nonce: u64,
+    pub valid_after: i64,
+    pub valid_until: i64,
+    pub instructions: &'a [MultiSigInstruction],
+    pub refund: Option<&'a Refund>,
+}
+
+impl SignedTx<'_> {
+    pub fn hash(&self) -> Result<[u8; 32]> {
+        create_multi_sig_tx_hash(
+            self.domain,
+            self.multisig_pda,
+            self.nonce,
+            self.valid_after,
+            self.valid_until,
+            self.instructions,
+            self.refund,
+        )
+        .map_err(|err| MultiSigErrors::from(err).into())
+    }
+}
+
+/// Returns the message the secp256k1 precompile has to carry for `mode`.
+pub fn precompile_message(mode: SigningMode, tx: &SignedTx) -> Result<Vec<u8>> {
+    Ok(match mode {
+        SigningMode::Raw => tx.hash()?.to_vec(),
+        SigningMode::PersonalSign => personal_sign_message(&tx.hash()?),
+        SigningMode::Eip712 => eip712::typed_data_message(tx),
+        SigningMode::SafeTx => safe_tx::safe_tx_message(tx)?,
+    })
+}
+
+/// Returns the message the secp256k1 precompile has to carry to cancel `nonce` in `mode`.
+pub fn cancel_message(
+    mode: SigningMode,
+    domain: &TxHashDomain,
+    evm_chain_id: u64,
+    multisig_pda: Pubkey,
+    nonce: u64,
+) -> Result<Vec<u8>> {
+    let cancel_hash =
+        || create_cancel_hash(domain, multisig_pda, nonce).map_err(MultiSigErrors::from);
+    Ok(match mode {
+        SigningMode::Raw => cancel_hash()?.to_vec(),
+        SigningMode::PersonalSign => personal_sign_message(&cancel_hash()?),
+        SigningMode::Eip712 => eip712::cancel_message(domain, evm_chain_id, multisig_pda, nonce),
+        SigningMode::SafeTx => safe_tx::rejection_message(evm_chain_id, multisig_pda, nonce),
+    })
+}
+
+pub fn personal_sign_message(tx_hash: &[u8; 32]) -> Vec<u8> {
+    [PERSONAL_SIGN_PREFIX, tx_hash].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::keccak;
+
+    // matches `hashMessage` of ethers / `personal_sign` of wallets for a 32 byte message
+    #[test]
+    fn personal_sign_digest() {
+        let tx_hash: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let message = personal_sign_message(&tx_hash);
+
+        assert_eq!(message.len(), 28 + 32);
+        let digest: String = keccak::hash(&message)
+            .to_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(
+            digest,
+            "04c3a0e6f47dd8889a200887da01ab4fa88d85f15fb01537cd4b7bcc1ef6f991"
+        );
+    }
+}
@@ -1,49 +1,67 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::instruction::Instruction;
 use anchor_lang::solana_program::secp256k1_program::ID as SECP256K1_ID;
+use precompile_parser::read_bytes;
+use precompile_parser::secp256k1::{self, ETH_ADDRESS_LEN};
 
 use crate::errors::MultiSigErrors;
 
-const SIGNATURE_LEN: usize = 64;
-const RECOVERY_ID_LEN: usize = 1;
-const ETH_ADDRESS_LEN: usize = 20;
-const OFFSET_METADATA_SIZE: usize = 11;
-const INSTRUCTION_INFO_SIZE: usize = 1;
-const MESSAGE_LEN: usize = 32;
-
-pub fn verify(ix: &Instruction, signers: Vec<[u8; 20]>, multi_sig_hash: [u8; 32]) -> Result<()> {
+/// Verifies the signatures of one secp256k1 precompile instruction against the leading `signers`
+/// and returns how many of them it covered. `message` is the signed preimage, see `signing`.
+pub fn verify(
+    ix: &Instruction,
+    ix_index: u16,
+    signers: &[[u8; 20]],
+    message: &[u8],
+) -> Result<usize> {
     // ref to the layout: https://docs.anza.xyz/runtime/programs#secp256k1-program
     if ix.program_id != SECP256K1_ID || !ix.accounts.is_empty() {
         return Err(MultiSigErrors::InvalidSecp256k1Instruction.into());
     }
 
-    // signatures count + offset metadata for each signer
-    let header_size = INSTRUCTION_INFO_SIZE + (OFFSET_METADATA_SIZE * signers.len());
+    let count = *ix
+        .data
+        .first()
+        .ok_or(MultiSigErrors::TruncatedPrecompileHeader)? as usize;
+    require_gte!(signers.len(), count, MultiSigErrors::SignatureCountMismatch);
 
-    // ethereum addresses come first after header
-    let addresses_start = header_size;
-    // then all signatures with recovery IDs
-    let signatures_start = addresses_start + (signers.len() * ETH_ADDRESS_LEN);
-    // then all messages
-    let messages_start = signatures_start + (signers.len() * (SIGNATURE_LEN + RECOVERY_ID_LEN));
+    let records = secp256k1::parse(&ix.data, count).map_err(MultiSigErrors::from)?;
+
+    for (offsets, signer) in records.iter().zip(signers.iter()) {
+        // secp256k1 offsets use absolute indices, only trust data read from the precompile itself
+        require!(
+            offsets.signature_instruction_index == ix_index
+                && offsets.public_key_instruction_index == ix_index
+                && offsets.message_instruction_index == ix_index,
+            MultiSigErrors::InvalidInstructionIndex
+        );
 
-    for (i, signer) in signers.iter().enumerate() {
         // verify ethereum address
-        let addr_offset = addresses_start + (i * ETH_ADDRESS_LEN);
-        let ix_addr_bytes = &ix.data[addr_offset..addr_offset + ETH_ADDRESS_LEN];
+        let ix_addr_bytes = read_bytes(
+            &ix.data,
+            offsets.public_key_offset as usize,
+            ETH_ADDRESS_LEN,
+        )
+        .map_err(MultiSigErrors::from)?;
         require!(
             ix_addr_bytes.eq(signer),
             MultiSigErrors::InvalidMessageSigner
         );
 
         // verify message
-        let msg_offset = messages_start + (i * MESSAGE_LEN);
-        let ix_msg_bytes = &ix.data[msg_offset..msg_offset + MESSAGE_LEN];
-        require!(
-            ix_msg_bytes.eq(&multi_sig_hash),
+        require_eq!(
+            offsets.message_data_size as usize,
+            message.len(),
             MultiSigErrors::InvalidMessage
         );
+        let ix_msg_bytes = read_bytes(
+            &ix.data,
+            offsets.message_data_offset as usize,
+            message.len(),
+        )
+        .map_err(MultiSigErrors::from)?;
+        require!(ix_msg_bytes.eq(message), MultiSigErrors::InvalidMessage);
     }
 
-    Ok(())
+    Ok(count)
 }
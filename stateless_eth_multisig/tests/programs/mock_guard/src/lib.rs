@@ -0,0 +1,63 @@
+use anchor_lang::prelude::*;
+use multisig_common::refund::Refund;
+use multisig_common::MultiSigInstruction;
+
+declare_id!("GQAbFL24a5Cx7pNiVmuae6uGBmY5ikaX9hZShgtRHDjh");
+
+/// Largest refund the guard lets the owners pay.
+pub const MAX_REFUND: u64 = 10_000;
+/// Lamports the multisig pda has to keep once a transaction executed.
+pub const MIN_BALANCE: u64 = 1_000_000_000;
+
+// a guard for the tests of the hooks, each hook logs the nonce it checked so the tests can
+// tell which of them ran
+#[program]
+pub mod mock_guard {
+    use super::*;
+
+    pub fn check_transaction(
+        _ctx: Context<Check>,
+        _config: Pubkey,
+        nonce: u64,
+        _instructions: Vec<MultiSigInstruction>,
+        refund: Option<Refund>,
+    ) -> Result<()> {
+        msg!("check_transaction of nonce {}", nonce);
+        if let Some(refund) = refund {
+            require_gte!(MAX_REFUND, refund.amount, GuardError::RefundTooHigh);
+        }
+        Ok(())
+    }
+
+    pub fn check_after_execution(
+        ctx: Context<Check>,
+        _config: Pubkey,
+        nonce: u64,
+        _instructions: Vec<MultiSigInstruction>,
+        _refund: Option<Refund>,
+    ) -> Result<()> {
+        msg!("check_after_execution of nonce {}", nonce);
+        require_gte!(
+            ctx.accounts.multisig_pda.lamports(),
+            MIN_BALANCE,
+            GuardError::BalanceTooLow
+        );
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct Check<'info> {
+    /// CHECK: the config of the multisig calling the hook
+    pub config: UncheckedAccount<'info>,
+    /// CHECK: the pda the transaction is executed for
+    pub multisig_pda: UncheckedAccount<'info>,
+}
+
+#[error_code]
+pub enum GuardError {
+    #[msg("refund is above the limit of the guard")]
+    RefundTooHigh,
+    #[msg("multisig pda holds less than the minimum balance of the guard")]
+    BalanceTooLow,
+}
@@ -1,6 +1,7 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { StatelessEthMultisig } from "../target/types/stateless_eth_multisig";
+import { MockGuard } from "../target/types/mock_guard";
 import {
   PublicKey,
   SystemProgram,
@@ -9,51 +10,46 @@ import {
 } from "@solana/web3.js";
 import { expect } from "chai";
 import { BatchSecp256k1Signer } from "../utils/secp256k1";
-import { keccak_256 } from "js-sha3";
-import { Wallet } from "ethers";
+import {
+  MultiSigInstruction,
+  Refund,
+  ROLE_PROPOSER,
+  ROLE_VOTER,
+  TX_HASH_V1,
+  TX_HASH_V2,
+  EthTxHashDomain,
+  createCancelHash,
+  createMultiSigBatchTxHash,
+  createMultiSigTxHash,
+  findAllowancePda,
+  findConfigPda,
+  findNonceLanePda,
+  findVaultPda,
+  findVaultSignerPda,
+  EIP712_TYPES,
+  eip712Domain,
+  eip712CancelMessage,
+  eip712CancelValue,
+  eip712Message,
+  eip712Value,
+  getChainId,
+  laneNonce,
+  parseCpiEvents,
+  personalSignMessage,
+  safeRejectionValue,
+  SAFE_TX_TYPES,
+  safeTxDomain,
+  safeTxMessage,
+  safeTxValue,
+} from "../utils/multisig";
+import { Signature, Wallet, getBytes, keccak256 } from "ethers";
 
 describe("secp256k1-multisig", () => {
-  function numberToLEBytes(
-    num: number | anchor.BN,
-    length: number = 8
-  ): Buffer {
-    const buf = Buffer.alloc(length);
-    if (num instanceof anchor.BN) {
-      buf.writeBigUInt64LE(BigInt(num.toString()));
-    } else {
-      buf.writeBigUInt64LE(BigInt(num));
-    }
-    return buf;
-  }
-
-  interface TransactionAccount {
-    pubkey: PublicKey;
-    isSigner: boolean;
-    isWritable: boolean;
-  }
-
-  function createMultiSigTxHash(
-    multisigPda: PublicKey,
-    nonce: anchor.BN,
-    accounts: TransactionAccount[],
-    data: Buffer,
-    program: PublicKey
-  ): Buffer {
-    const payload: Buffer[] = [];
-
-    payload.push(Buffer.from(multisigPda.toBytes()));
-    payload.push(numberToLEBytes(nonce));
-    for (const account of accounts) {
-      payload.push(Buffer.from(account.pubkey.toBytes()));
-      payload.push(Buffer.from([account.isSigner ? 1 : 0]));
-      payload.push(Buffer.from([account.isWritable ? 1 : 0]));
-    }
-    payload.push(Buffer.from(program.toBytes()));
-    payload.push(data);
-
-    const concatenatedPayload = Buffer.concat(payload);
-    return Buffer.from(keccak_256.arrayBuffer(concatenatedPayload));
-  }
+  // validity window that never closes, tests narrow it where needed
+  const openWindow = {
+    validAfter: new anchor.BN(0),
+    validUntil: new anchor.BN("9223372036854775807"),
+  };
 
   const provider = anchor.AnchorProvider.env();
   anchor.setProvider(provider);
@@ -75,6 +71,172 @@ describe("secp256k1-multisig", () => {
 
   let configAccount: PublicKey;
   let multisigPda: PublicKey;
+  let domain: EthTxHashDomain;
+
+  before(async () => {
+    domain = {
+      programId: program.programId,
+      chainId: await getChainId(provider.connection),
+      version: TX_HASH_V2,
+      evmChainId: 1,
+    };
+  });
+
+  const toAddress = (wallet: { address: string }) =>
+    Buffer.from(wallet.address.slice(2), "hex");
+
+  // creates a config, by default of owner1, owner2 and owner3 with a threshold
+  // of 2, and funds its multisig pda
+  async function createConfig(
+    opts: {
+      owners?: { address: string }[];
+      weights?: number[];
+      threshold?: number;
+      evmChainId?: number;
+      hashVersion?: number;
+    } = {}
+  ) {
+    const configKeypair = anchor.web3.Keypair.generate();
+    const configAccount = configKeypair.publicKey;
+    const multisigPda = findVaultSignerPda(program.programId, configAccount, 0);
+    const owners = opts.owners ?? [owner1, owner2, owner3];
+
+    const signature = await program.methods
+      .create(
+        owners.map(toAddress),
+        opts.weights ?? owners.map(() => 1),
+        opts.threshold ?? 2,
+        domain.chainId,
+        new anchor.BN(opts.evmChainId ?? domain.evmChainId),
+        opts.hashVersion ?? domain.version
+      )
+      .accounts({
+        config: configAccount,
+        payer: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([configKeypair])
+      .rpc({ commitment: "confirmed" });
+
+    await airdropSol(multisigPda);
+    return { configAccount, multisigPda, signature };
+  }
+
+  // executes `ix` through `configAccount`, by default approved by owner2 and
+  // owner3 for the pending nonce of the multisig pda
+  async function executeSigned(
+    configAccount: PublicKey,
+    ix: TransactionInstruction,
+    opts: {
+      signers?: { address: string; privateKey: string }[];
+      nonce?: anchor.BN;
+      window?: { validAfter: anchor.BN; validUntil: anchor.BN };
+      hashVersion?: number;
+      vaultIndex?: number;
+      // the vault whose pda the owners approve for, `vaultIndex` by default
+      signedIndex?: number;
+      nonceLane?: number;
+      guard?: PublicKey;
+      executor?: anchor.web3.Keypair;
+      refund?: Refund;
+      // the refund the owners approve, `refund` by default
+      signedRefund?: Refund | null;
+      refundReceiver?: PublicKey;
+    } = {}
+  ) {
+    const signers = opts.signers ?? [owner2, owner3];
+    const vaultIndex = opts.vaultIndex ?? 0;
+    const vault = findVaultPda(program.programId, configAccount, vaultIndex);
+    const vaultPda = findVaultSignerPda(
+      program.programId,
+      configAccount,
+      vaultIndex
+    );
+    // every vault but the multisig pda counts its own nonces
+    const nonce =
+      opts.nonce ??
+      (vaultIndex === 0
+        ? await program.account.multiSigConfig.fetch(configAccount)
+        : await program.account.vault.fetch(vault)
+      ).nonce;
+    const accounts = ix.keys.map((key) => ({
+      pubkey: key.pubkey,
+      isSigner: key.pubkey.equals(vaultPda) ? false : key.isSigner,
+      isWritable: key.isWritable,
+    }));
+
+    const executeParams = {
+      programId: ix.programId,
+      accounts: accounts,
+      data: ix.data,
+      signers: signers.map(toAddress),
+      nonce,
+      ...(opts.window ?? openWindow),
+      precompileIxIndices: [0],
+      vaultIndex,
+      signingMode: { raw: {} },
+      refund: opts.refund ?? null,
+    };
+
+    const signedRefund =
+      opts.signedRefund === undefined ? opts.refund : opts.signedRefund;
+    const txHash = createMultiSigTxHash(
+      { ...domain, version: opts.hashVersion ?? domain.version },
+      findVaultSignerPda(
+        program.programId,
+        configAccount,
+        opts.signedIndex ?? vaultIndex
+      ),
+      executeParams.nonce,
+      executeParams.validAfter,
+      executeParams.validUntil,
+      executeParams.accounts,
+      Buffer.from(executeParams.data),
+      executeParams.programId,
+      signedRefund ?? undefined
+    );
+
+    const secp256k1Ix =
+      BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction(
+        signers.map((wallet) => ({
+          privateKey: Buffer.from(wallet.privateKey.slice(2), "hex"),
+          message: txHash,
+        }))
+      );
+
+    const signature = await program.methods
+      .execute(executeParams)
+      .accounts({
+        config: configAccount,
+        multisigPda: findVaultSignerPda(program.programId, configAccount, 0),
+        vault: vaultIndex === 0 ? null : vault,
+        vaultPda: vaultIndex === 0 ? null : vaultPda,
+        nonceLane:
+          opts.nonceLane === undefined
+            ? null
+            : findNonceLanePda(
+                program.programId,
+                configAccount,
+                opts.nonceLane
+              ),
+        guard: opts.guard ?? null,
+        executor: opts.executor?.publicKey ?? null,
+        refundReceiver: opts.refundReceiver ?? null,
+      })
+      .remainingAccounts([
+        ...accounts,
+        {
+          pubkey: ix.programId,
+          isSigner: false,
+          isWritable: false,
+        },
+      ])
+      .preInstructions([secp256k1Ix])
+      .signers(opts.executor ? [opts.executor] : [])
+      .rpc({ commitment: "confirmed" });
+
+    return { signature, txHash };
+  }
 
   it("creates a multisig account", async () => {
     const configKeypair = anchor.web3.Keypair.generate();
@@ -95,7 +257,14 @@ describe("secp256k1-multisig", () => {
 
     console.log(owners, threshold);
     await program.methods
-      .create(owners, threshold)
+      .create(
+        owners,
+        [1, 1, 1],
+        threshold,
+        domain.chainId,
+        new anchor.BN(domain.evmChainId),
+        domain.version
+      )
       .accounts({
         config: configAccount,
         payer: provider.wallet.publicKey,
@@ -109,11 +278,24 @@ describe("secp256k1-multisig", () => {
     expect(buffersToArrays(account.owners)).to.deep.equal(
       buffersToArrays(owners)
     );
+    expect(account.weights).to.deep.equal([1, 1, 1]);
     expect(account.threshold).to.equal(threshold);
     expect(account.nonce.toString()).to.equal("0");
+    expect(Buffer.from(account.chainId)).to.deep.equal(domain.chainId);
+    expect(account.evmChainId.toNumber()).to.equal(domain.evmChainId);
+    expect(account.hashVersion).to.equal(TX_HASH_V2);
     expect(account.multisigPda.toString()).to.equal(multisigPda.toString());
   });
 
+  it("rejects an EVM chain id of 0", async () => {
+    try {
+      await createConfig({ evmChainId: 0 });
+      expect.fail("should have rejected chain id 0");
+    } catch (e) {
+      expect(e.toString()).to.include("InvalidEvmChainId");
+    }
+  });
+
   it("execute a transfer through the multisig", async () => {
     const connection = provider.connection;
     await airdropSol(multisigPda);
@@ -162,11 +344,18 @@ describe("secp256k1-multisig", () => {
         Buffer.from(addr.slice(2), "hex")
       ),
       nonce: new anchor.BN(0),
+      ...openWindow,
+      precompileIxIndices: [0],
+      vaultIndex: 0,
+      signingMode: { raw: {} },
     };
 
     const txHash = createMultiSigTxHash(
+      domain,
       multisigPda,
       executeParams.nonce,
+      executeParams.validAfter,
+      executeParams.validUntil,
       executeParams.accounts,
       Buffer.from(executeParams.data),
       executeParams.programId
@@ -230,33 +419,7 @@ describe("secp256k1-multisig", () => {
     let safeTransferAmount: number;
 
     beforeEach(async () => {
-      const configKeypair = anchor.web3.Keypair.generate();
-      configAccount = configKeypair.publicKey;
-
-      const [pda] = await PublicKey.findProgramAddress(
-        [Buffer.from("multisig-signer"), configAccount.toBuffer()],
-        program.programId
-      );
-      multisigPda = pda;
-
-      // Convert Ethereum addresses to the format expected by the program
-      const owners = [owner1, owner2, owner3].map((wallet) => {
-        const addr = wallet.address.slice(2); // Remove '0x' prefix
-        return Buffer.from(addr, "hex");
-      });
-      const threshold = 2;
-
-      await program.methods
-        .create(owners, threshold)
-        .accounts({
-          config: configAccount,
-          payer: provider.wallet.publicKey,
-          systemProgram: SystemProgram.programId,
-        })
-        .signers([configKeypair])
-        .rpc({ commitment: "confirmed" });
-
-      await airdropSol(multisigPda);
+      ({ configAccount, multisigPda } = await createConfig());
 
       const balanceBeforeTransfer = await provider.connection.getBalance(
         multisigPda
@@ -271,7 +434,8 @@ describe("secp256k1-multisig", () => {
     async function createTransferTx(
       amount: number,
       customSigners?: Buffer[],
-      nonce?: anchor.BN
+      nonce?: anchor.BN,
+      window?: { validAfter: anchor.BN; validUntil: anchor.BN }
     ) {
       const transferIx = SystemProgram.transfer({
         fromPubkey: multisigPda,
@@ -294,11 +458,18 @@ describe("secp256k1-multisig", () => {
           Buffer.from(owner3.address.slice(2), "hex"),
         ],
         nonce: nonce || new anchor.BN(0),
+        ...(window || openWindow),
+        precompileIxIndices: [0],
+        vaultIndex: 0,
+        signingMode: { raw: {} },
       };
 
       const txHash = createMultiSigTxHash(
+        domain,
         multisigPda,
         executeParams.nonce,
+        executeParams.validAfter,
+        executeParams.validUntil,
         executeParams.accounts,
         Buffer.from(executeParams.data),
         executeParams.programId
@@ -459,7 +630,7 @@ describe("secp256k1-multisig", () => {
           logs: error.logs,
           error: error.error,
         });
-        expect(e.toString()).to.include("InvalidArgument");
+        expect(e.toString()).to.include("InvalidSecp256k1Instruction");
       }
     });
     it("prevents nonce replay", async () => {
@@ -728,5 +899,2839 @@ describe("secp256k1-multisig", () => {
         expect(e.toString()).to.include("ConstraintSeeds");
       }
     });
+
+    async function executeTransferWithin(window: {
+      validAfter: anchor.BN;
+      validUntil: anchor.BN;
+    }) {
+      const transferIx = SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: recipient,
+        lamports: safeTransferAmount,
+      });
+
+      await executeSigned(configAccount, transferIx, { window });
+    }
+
+    it("rejects a transaction before its validity window", async () => {
+      const now = Math.floor(Date.now() / 1000);
+
+      try {
+        await executeTransferWithin({
+          validAfter: new anchor.BN(now + 3600),
+          validUntil: openWindow.validUntil,
+        });
+        expect.fail("should have rejected an early transaction");
+      } catch (e) {
+        expect(e.toString()).to.include("TransactionTooEarly");
+      }
+    });
+
+    it("rejects an expired transaction", async () => {
+      const now = Math.floor(Date.now() / 1000);
+
+      try {
+        await executeTransferWithin({
+          validAfter: openWindow.validAfter,
+          validUntil: new anchor.BN(now - 3600),
+        });
+        expect.fail("should have rejected an expired transaction");
+      } catch (e) {
+        expect(e.toString()).to.include("TransactionExpired");
+      }
+    });
+
+    it("rejects signatures made for another cluster", async () => {
+      const config = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      const { executeParams } = await createTransferTx(
+        safeTransferAmount,
+        undefined,
+        config.nonce
+      );
+
+      // same config and transaction, signed under a different chain id
+      const foreignHash = createMultiSigTxHash(
+        { ...domain, chainId: Buffer.alloc(32) },
+        multisigPda,
+        executeParams.nonce,
+        executeParams.validAfter,
+        executeParams.validUntil,
+        executeParams.accounts,
+        Buffer.from(executeParams.data),
+        executeParams.programId
+      );
+
+      const secp256k1Ix =
+        await BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction([
+          {
+            privateKey: Buffer.from(owner2.privateKey.slice(2), "hex"),
+            message: foreignHash,
+          },
+          {
+            privateKey: Buffer.from(owner3.privateKey.slice(2), "hex"),
+            message: foreignHash,
+          },
+        ]);
+
+      const remainingAccounts = [
+        ...executeParams.accounts,
+        {
+          pubkey: SystemProgram.programId,
+          isSigner: false,
+          isWritable: false,
+        },
+      ];
+
+      try {
+        await program.methods
+          .execute(executeParams)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .remainingAccounts(remainingAccounts)
+          .preInstructions([secp256k1Ix])
+          .signers([])
+          .rpc();
+
+        expect.fail("should have rejected a foreign chain id");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidMessage");
+      }
+    });
+  });
+
+  describe("owner management", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    const owner4 = Wallet.createRandom();
+
+    const buffersToArrays = (buffers) => buffers.map((buf) => Array.from(buf));
+
+    before(async () => {
+      // the multisig PDA pays for growing the config account
+      ({ configAccount, multisigPda } = await createConfig());
+    });
+
+    const executeConfigChange = (
+      ix: TransactionInstruction,
+      signers: { address: string; privateKey: string }[]
+    ) => executeSigned(configAccount, ix, { signers });
+
+    it("adds an owner and raises the threshold", async () => {
+      const ix = await program.methods
+        .addOwner(toAddress(owner4), 1, 3)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .instruction();
+
+      await executeConfigChange(ix, [owner2, owner3]);
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(buffersToArrays(account.owners)).to.deep.equal(
+        buffersToArrays([owner1, owner2, owner3, owner4].map(toAddress))
+      );
+      expect(account.threshold).to.equal(3);
+      expect(account.nonce.toString()).to.equal("1");
+    });
+
+    it("swaps an owner", async () => {
+      const ix = await program.methods
+        .swapOwner(toAddress(owner1), toAddress(owner4))
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      try {
+        await executeConfigChange(ix, [owner2, owner3, owner4]);
+        expect.fail("should have rejected a duplicate owner");
+      } catch (e) {
+        expect(e.toString()).to.include("DuplicateSigner");
+      }
+
+      const newOwner = Wallet.createRandom();
+      const swapIx = await program.methods
+        .swapOwner(toAddress(owner1), toAddress(newOwner))
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      await executeConfigChange(swapIx, [owner2, owner3, owner4]);
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(buffersToArrays(account.owners)).to.deep.equal(
+        buffersToArrays([newOwner, owner2, owner3, owner4].map(toAddress))
+      );
+    });
+
+    it("removes an owner and lowers the threshold", async () => {
+      const sizeBefore = (
+        await provider.connection.getAccountInfo(configAccount)
+      ).data.length;
+
+      const ix = await program.methods
+        .removeOwner(toAddress(owner4), 2)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .instruction();
+
+      await executeConfigChange(ix, [owner2, owner3, owner4]);
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(account.owners.length).to.equal(3);
+      expect(account.threshold).to.equal(2);
+
+      const sizeAfter = (
+        await provider.connection.getAccountInfo(configAccount)
+      ).data.length;
+      expect(sizeBefore - sizeAfter).to.equal(21);
+    });
+
+    it("rejects a threshold above the owner count", async () => {
+      const ix = await program.methods
+        .changeThreshold(4)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      try {
+        await executeConfigChange(ix, [owner2, owner3]);
+        expect.fail("should have rejected an invalid threshold");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidThreshold");
+      }
+    });
+
+    it("rejects a zero weight", async () => {
+      const ix = await program.methods
+        .changeWeight(toAddress(owner3), 0)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      try {
+        await executeConfigChange(ix, [owner2, owner3]);
+        expect.fail("should have rejected a zero weight");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidWeights");
+      }
+    });
+
+    it("changes an owner weight", async () => {
+      const ix = await program.methods
+        .changeWeight(toAddress(owner2), 2)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      await executeConfigChange(ix, [owner2, owner3]);
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(account.weights).to.deep.equal([1, 2, 1]);
+    });
+
+    it("changes the threshold", async () => {
+      const ix = await program.methods
+        .changeThreshold(3)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      await executeConfigChange(ix, [owner2, owner3]);
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(account.threshold).to.equal(3);
+    });
+
+    it("rejects signers below the weighted threshold", async () => {
+      const ix = await program.methods
+        .changeThreshold(2)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      // owner2 carries a weight of 2, one short of the threshold
+      try {
+        await executeConfigChange(ix, [owner2]);
+        expect.fail("should have rejected an insufficient weight");
+      } catch (e) {
+        expect(e.toString()).to.include("ThresholdNotMet");
+      }
+
+      await executeConfigChange(ix, [owner2, owner3]);
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(account.threshold).to.equal(2);
+    });
+
+    it("rejects config changes not signed by the multisig PDA", async () => {
+      try {
+        await program.methods
+          .changeThreshold(1)
+          .accounts({
+            config: configAccount,
+            multisigPda: provider.wallet.publicKey,
+          })
+          .rpc();
+
+        expect.fail("should have rejected a foreign signer");
+      } catch (e) {
+        expect(e.toString()).to.include("ConstraintHasOne");
+      }
+    });
+  });
+
+  describe("batch execution", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+
+    beforeEach(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+    });
+
+    function toMultiSigInstruction(
+      ix: TransactionInstruction
+    ): MultiSigInstruction {
+      return {
+        programId: ix.programId,
+        accounts: ix.keys.map((key) => ({
+          pubkey: key.pubkey,
+          isSigner: key.pubkey.equals(multisigPda) ? false : key.isSigner,
+          isWritable: key.isWritable,
+        })),
+        data: ix.data,
+      };
+    }
+
+    async function executeBatch(instructions: MultiSigInstruction[]) {
+      const executeParams = {
+        instructions,
+        signers: [owner2, owner3].map((wallet) =>
+          Buffer.from(wallet.address.slice(2), "hex")
+        ),
+        nonce: new anchor.BN(0),
+        ...openWindow,
+        precompileIxIndices: [0],
+        vaultIndex: 0,
+        signingMode: { raw: {} },
+      };
+
+      const txHash = createMultiSigBatchTxHash(
+        domain,
+        multisigPda,
+        executeParams.nonce,
+        executeParams.validAfter,
+        executeParams.validUntil,
+        executeParams.instructions
+      );
+
+      const secp256k1Ix =
+        await BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction([
+          {
+            privateKey: Buffer.from(owner2.privateKey.slice(2), "hex"),
+            message: txHash,
+          },
+          {
+            privateKey: Buffer.from(owner3.privateKey.slice(2), "hex"),
+            message: txHash,
+          },
+        ]);
+
+      const remainingAccounts = [
+        ...instructions.flatMap((instruction) => instruction.accounts),
+        {
+          pubkey: SystemProgram.programId,
+          isSigner: false,
+          isWritable: false,
+        },
+      ];
+
+      await program.methods
+        .executeBatch(executeParams)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .remainingAccounts(remainingAccounts)
+        .preInstructions([secp256k1Ix])
+        .signers([])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    it("executes several instructions under one nonce", async () => {
+      const recipientA = anchor.web3.Keypair.generate().publicKey;
+      const recipientB = anchor.web3.Keypair.generate().publicKey;
+      const amount = anchor.web3.LAMPORTS_PER_SOL / 2;
+
+      await executeBatch(
+        [recipientA, recipientB].map((recipient) =>
+          toMultiSigInstruction(
+            SystemProgram.transfer({
+              fromPubkey: multisigPda,
+              toPubkey: recipient,
+              lamports: amount,
+            })
+          )
+        )
+      );
+
+      expect(await provider.connection.getBalance(recipientA)).to.equal(
+        amount
+      );
+      expect(await provider.connection.getBalance(recipientB)).to.equal(
+        amount
+      );
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(account.nonce.toString()).to.equal("1");
+    });
+
+    it("reverts the whole batch when one instruction fails", async () => {
+      const recipientA = anchor.web3.Keypair.generate().publicKey;
+      const recipientB = anchor.web3.Keypair.generate().publicKey;
+
+      let reverted = false;
+      try {
+        await executeBatch([
+          toMultiSigInstruction(
+            SystemProgram.transfer({
+              fromPubkey: multisigPda,
+              toPubkey: recipientA,
+              lamports: anchor.web3.LAMPORTS_PER_SOL / 2,
+            })
+          ),
+          // more than the vault holds
+          toMultiSigInstruction(
+            SystemProgram.transfer({
+              fromPubkey: multisigPda,
+              toPubkey: recipientB,
+              lamports: 10 * anchor.web3.LAMPORTS_PER_SOL,
+            })
+          ),
+        ]);
+      } catch (e) {
+        reverted = true;
+      }
+      expect(reverted).to.equal(true);
+
+      expect(await provider.connection.getBalance(recipientA)).to.equal(0);
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(account.nonce.toString()).to.equal("0");
+    });
+
+    it("rejects an empty batch", async () => {
+      try {
+        await executeBatch([]);
+        expect.fail("should have rejected an empty batch");
+      } catch (e) {
+        expect(e.toString()).to.include("EmptyBatch");
+      }
+    });
+  });
+
+  describe("precompile instruction indices", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    let recipient: PublicKey;
+
+    beforeEach(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+      recipient = anchor.web3.Keypair.generate().publicKey;
+    });
+
+    function createTransferParams(precompileIxIndices: number[]) {
+      const transferIx = SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: recipient,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 2,
+      });
+
+      const accounts = transferIx.keys.map((key) => ({
+        pubkey: key.pubkey,
+        isSigner: key.pubkey.equals(multisigPda) ? false : key.isSigner,
+        isWritable: key.isWritable,
+      }));
+
+      const executeParams = {
+        programId: SystemProgram.programId,
+        accounts: accounts,
+        data: transferIx.data,
+        signers: [owner2, owner3].map((wallet) =>
+          Buffer.from(wallet.address.slice(2), "hex")
+        ),
+        nonce: new anchor.BN(0),
+        ...openWindow,
+        precompileIxIndices,
+        vaultIndex: 0,
+        signingMode: { raw: {} },
+      };
+
+      const txHash = createMultiSigTxHash(
+        domain,
+        multisigPda,
+        executeParams.nonce,
+        executeParams.validAfter,
+        executeParams.validUntil,
+        executeParams.accounts,
+        Buffer.from(executeParams.data),
+        executeParams.programId
+      );
+
+      const remainingAccounts = [
+        ...accounts,
+        {
+          pubkey: SystemProgram.programId,
+          isSigner: false,
+          isWritable: false,
+        },
+      ];
+
+      return { executeParams, txHash, remainingAccounts };
+    }
+
+    it("allows other instructions between the precompile and execute", async () => {
+      const { executeParams, txHash, remainingAccounts } =
+        createTransferParams([0]);
+
+      const secp256k1Ix =
+        await BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction([
+          {
+            privateKey: Buffer.from(owner2.privateKey.slice(2), "hex"),
+            message: txHash,
+          },
+          {
+            privateKey: Buffer.from(owner3.privateKey.slice(2), "hex"),
+            message: txHash,
+          },
+        ]);
+
+      await program.methods
+        .execute(executeParams)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .remainingAccounts(remainingAccounts)
+        .preInstructions([
+          secp256k1Ix,
+          anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+            units: 400_000,
+          }),
+        ])
+        .signers([])
+        .rpc({ commitment: "confirmed" });
+
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        anchor.web3.LAMPORTS_PER_SOL / 2
+      );
+    });
+
+    it("collects signatures from several precompile instructions", async () => {
+      const { executeParams, txHash, remainingAccounts } =
+        createTransferParams([0, 1]);
+
+      const firstIx = BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction(
+          [
+            {
+              privateKey: Buffer.from(owner2.privateKey.slice(2), "hex"),
+              message: txHash,
+            },
+          ],
+          0
+        );
+      const secondIx = BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction(
+          [
+            {
+              privateKey: Buffer.from(owner3.privateKey.slice(2), "hex"),
+              message: txHash,
+            },
+          ],
+          1
+        );
+
+      await program.methods
+        .execute(executeParams)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .remainingAccounts(remainingAccounts)
+        .preInstructions([firstIx, secondIx])
+        .signers([])
+        .rpc({ commitment: "confirmed" });
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(account.nonce.toString()).to.equal("1");
+    });
+
+    it("rejects indices that do not cover every signer", async () => {
+      const { executeParams, txHash, remainingAccounts } =
+        createTransferParams([0]);
+
+      const firstIx = BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction(
+          [
+            {
+              privateKey: Buffer.from(owner2.privateKey.slice(2), "hex"),
+              message: txHash,
+            },
+          ],
+          0
+        );
+      const secondIx = BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction(
+          [
+            {
+              privateKey: Buffer.from(owner3.privateKey.slice(2), "hex"),
+              message: txHash,
+            },
+          ],
+          1
+        );
+
+      try {
+        await program.methods
+          .execute(executeParams)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .remainingAccounts(remainingAccounts)
+          .preInstructions([firstIx, secondIx])
+          .signers([])
+          .rpc();
+
+        expect.fail("should have rejected unverified signers");
+      } catch (e) {
+        expect(e.toString()).to.include("SignatureCountMismatch");
+      }
+    });
+  });
+
+  describe("hash versions", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig({
+        hashVersion: TX_HASH_V1,
+      }));
+    });
+
+    it("matches the vectors pinned in the program", () => {
+      const hashWith = (version: number) =>
+        createMultiSigTxHash(
+          {
+            programId: new PublicKey(Buffer.alloc(32, 1)),
+            chainId: Buffer.alloc(32, 2),
+            version,
+          },
+          new PublicKey(Buffer.alloc(32, 3)),
+          new anchor.BN(5),
+          openWindow.validAfter,
+          openWindow.validUntil,
+          [
+            {
+              pubkey: new PublicKey(Buffer.alloc(32, 7)),
+              isSigner: true,
+              isWritable: false,
+            },
+          ],
+          Buffer.from([0xaa, 0xbb]),
+          new PublicKey(Buffer.alloc(32, 8))
+        ).toString("hex");
+
+      expect(hashWith(TX_HASH_V1)).to.equal(
+        "cf66c1ff7270f16e1b9d00094512e1f37ad8e1ecafc703af782312d30f2d1224"
+      );
+      expect(hashWith(TX_HASH_V2)).to.equal(
+        "bc7513653a6194454d0130570017c7361686afefb3633ede4d7738172ac5b674"
+      );
+    });
+
+    it("rejects an unsupported hash version", async () => {
+      try {
+        await createConfig({ hashVersion: 3 });
+        expect.fail("should have rejected an unknown hash version");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidHashVersion");
+      }
+    });
+
+    it("executes with the legacy layout on a v1 config", async () => {
+      const recipient = anchor.web3.Keypair.generate().publicKey;
+      const transferIx = SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: recipient,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 2,
+      });
+
+      await executeSigned(configAccount, transferIx, {
+        hashVersion: TX_HASH_V1,
+      });
+
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        anchor.web3.LAMPORTS_PER_SOL / 2
+      );
+    });
+
+    it("switches a config to the canonical layout", async () => {
+      const ix = await program.methods
+        .changeHashVersion(TX_HASH_V2)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      await executeSigned(configAccount, ix, { hashVersion: TX_HASH_V1 });
+
+      const account = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      expect(account.hashVersion).to.equal(TX_HASH_V2);
+    });
+  });
+
+  describe("wallet signing modes", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    let recipient: PublicKey;
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+      recipient = anchor.web3.Keypair.generate().publicKey;
+    });
+
+    async function createTransferParams(signingMode: object) {
+      const config = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+
+      const transferIx = SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: recipient,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 2,
+      });
+
+      const accounts = transferIx.keys.map((key) => ({
+        pubkey: key.pubkey,
+        isSigner: key.pubkey.equals(multisigPda) ? false : key.isSigner,
+        isWritable: key.isWritable,
+      }));
+
+      const executeParams = {
+        programId: SystemProgram.programId,
+        accounts: accounts,
+        data: transferIx.data,
+        signers: [owner2, owner3].map((wallet) =>
+          Buffer.from(wallet.address.slice(2), "hex")
+        ),
+        nonce: config.nonce,
+        ...openWindow,
+        precompileIxIndices: [0],
+        vaultIndex: 0,
+        signingMode,
+      };
+
+      const txHash = createMultiSigTxHash(
+        domain,
+        multisigPda,
+        executeParams.nonce,
+        executeParams.validAfter,
+        executeParams.validUntil,
+        executeParams.accounts,
+        Buffer.from(executeParams.data),
+        executeParams.programId
+      );
+
+      const remainingAccounts = [
+        ...accounts,
+        {
+          pubkey: SystemProgram.programId,
+          isSigner: false,
+          isWritable: false,
+        },
+      ];
+
+      return { executeParams, txHash, remainingAccounts };
+    }
+
+    // signs the way a browser wallet does and moves the signatures into a precompile instruction
+    async function personalSignIx(txHash: Buffer) {
+      const params = [];
+      for (const wallet of [owner2, owner3]) {
+        const signature = Signature.from(
+          await wallet.signMessage(getBytes(txHash))
+        );
+        params.push({
+          ethAddress: wallet.address,
+          message: personalSignMessage(txHash),
+          signature: Buffer.concat([
+            Buffer.from(signature.r.slice(2), "hex"),
+            Buffer.from(signature.s.slice(2), "hex"),
+          ]),
+          recoveryId: signature.yParity,
+        });
+      }
+
+      return BatchSecp256k1Signer.createVerifySignaturesInstruction(params);
+    }
+
+    // signs the typed data with `eth_signTypedData_v4` semantics
+    async function typedDataIx(
+      typedDomain: object,
+      types: typeof EIP712_TYPES | typeof SAFE_TX_TYPES,
+      value: Record<string, unknown>,
+      message: Buffer
+    ) {
+      const params = [];
+      for (const wallet of [owner2, owner3]) {
+        const signature = Signature.from(
+          await wallet.signTypedData(typedDomain, types, value)
+        );
+        params.push({
+          ethAddress: wallet.address,
+          message,
+          signature: Buffer.concat([
+            Buffer.from(signature.r.slice(2), "hex"),
+            Buffer.from(signature.s.slice(2), "hex"),
+          ]),
+          recoveryId: signature.yParity,
+        });
+      }
+
+      return BatchSecp256k1Signer.createVerifySignaturesInstruction(params);
+    }
+
+    function eip712Ix(value: ReturnType<typeof eip712Value>) {
+      return typedDataIx(
+        eip712Domain(domain),
+        EIP712_TYPES,
+        value,
+        eip712Message(domain, value)
+      );
+    }
+
+    // what a Safe owner signs when proposing the instruction to the multisig pda
+    function safeTxIx(executeParams: {
+      programId: PublicKey;
+      accounts: { pubkey: PublicKey; isSigner: boolean; isWritable: boolean }[];
+      data: Buffer;
+      nonce: anchor.BN;
+    }) {
+      const value = safeTxValue(executeParams.nonce, {
+        programId: executeParams.programId,
+        accounts: executeParams.accounts,
+        data: Buffer.from(executeParams.data),
+      });
+
+      return typedDataIx(
+        safeTxDomain(domain, multisigPda),
+        SAFE_TX_TYPES,
+        value,
+        safeTxMessage(domain, multisigPda, value)
+      );
+    }
+
+    function typedValue(executeParams: {
+      programId: PublicKey;
+      accounts: { pubkey: PublicKey; isSigner: boolean; isWritable: boolean }[];
+      data: Buffer;
+      nonce: anchor.BN;
+      validAfter: anchor.BN;
+      validUntil: anchor.BN;
+    }) {
+      return eip712Value(
+        multisigPda,
+        executeParams.nonce,
+        executeParams.validAfter,
+        executeParams.validUntil,
+        [
+          {
+            programId: executeParams.programId,
+            accounts: executeParams.accounts,
+            data: Buffer.from(executeParams.data),
+          },
+        ]
+      );
+    }
+
+    it("rejects personal_sign approvals in raw mode", async () => {
+      const { executeParams, txHash, remainingAccounts } =
+        await createTransferParams({ raw: {} });
+
+      try {
+        await program.methods
+          .execute(executeParams)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .remainingAccounts(remainingAccounts)
+          .preInstructions([await personalSignIx(txHash)])
+          .signers([])
+          .rpc();
+
+        expect.fail("should have rejected a prefixed message");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidMessage");
+      }
+    });
+
+    it("executes with personal_sign approvals", async () => {
+      const { executeParams, txHash, remainingAccounts } =
+        await createTransferParams({ personalSign: {} });
+
+      await program.methods
+        .execute(executeParams)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .remainingAccounts(remainingAccounts)
+        .preInstructions([await personalSignIx(txHash)])
+        .signers([])
+        .rpc({ commitment: "confirmed" });
+
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        anchor.web3.LAMPORTS_PER_SOL / 2
+      );
+    });
+
+    it("matches the EIP-712 vector pinned in the program", () => {
+      const vectorDomain = {
+        programId: new PublicKey(Buffer.alloc(32, 1)),
+        chainId: Buffer.alloc(32, 2),
+        version: TX_HASH_V2,
+        evmChainId: 1,
+      };
+      const message = eip712Message(
+        vectorDomain,
+        eip712Value(
+          new PublicKey(Buffer.alloc(32, 3)),
+          new anchor.BN(5),
+          openWindow.validAfter,
+          openWindow.validUntil,
+          [
+            {
+              programId: new PublicKey(Buffer.alloc(32, 8)),
+              accounts: [
+                {
+                  pubkey: new PublicKey(Buffer.alloc(32, 7)),
+                  isSigner: true,
+                  isWritable: false,
+                },
+              ],
+              data: Buffer.from([0xaa, 0xbb]),
+            },
+          ]
+        )
+      );
+
+      expect(message.subarray(2, 34).toString("hex")).to.equal(
+        "9493e3476f3f0977cc91c4fefdb430a2b6cbc868d879eb5a6e36439e3aa1183e"
+      );
+      expect(keccak256(message)).to.equal(
+        "0xe002423f7ea13f3e6fbd3adb320423702df9e43c01868de876cb6a399ee6edd4"
+      );
+    });
+
+    it("rejects typed data signed for another nonce", async () => {
+      const { executeParams, remainingAccounts } = await createTransferParams(
+        { eip712: {} }
+      );
+      const staleValue = typedValue({
+        ...executeParams,
+        nonce: executeParams.nonce.addn(1),
+      });
+
+      try {
+        await program.methods
+          .execute(executeParams)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .remainingAccounts(remainingAccounts)
+          .preInstructions([await eip712Ix(staleValue)])
+          .signers([])
+          .rpc();
+
+        expect.fail("should have rejected typed data for another nonce");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidMessage");
+      }
+    });
+
+    it("executes with EIP-712 approvals", async () => {
+      const balanceBefore = await provider.connection.getBalance(recipient);
+      const { executeParams, remainingAccounts } = await createTransferParams(
+        { eip712: {} }
+      );
+
+      await program.methods
+        .execute(executeParams)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .remainingAccounts(remainingAccounts)
+        .preInstructions([await eip712Ix(typedValue(executeParams))])
+        .signers([])
+        .rpc({ commitment: "confirmed" });
+
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        balanceBefore + anchor.web3.LAMPORTS_PER_SOL / 2
+      );
+    });
+
+    it("matches the SafeTx vector pinned in the program", () => {
+      const vectorDomain = {
+        programId: new PublicKey(Buffer.alloc(32, 1)),
+        chainId: Buffer.alloc(32, 2),
+        version: TX_HASH_V2,
+        evmChainId: 1,
+      };
+      const message = safeTxMessage(
+        vectorDomain,
+        new PublicKey(Buffer.alloc(32, 3)),
+        safeTxValue(new anchor.BN(5), {
+          programId: new PublicKey(Buffer.alloc(32, 8)),
+          accounts: [
+            {
+              pubkey: new PublicKey(Buffer.alloc(32, 7)),
+              isSigner: true,
+              isWritable: false,
+            },
+          ],
+          data: Buffer.from([0xaa, 0xbb]),
+        })
+      );
+
+      expect(message.subarray(2, 34).toString("hex")).to.equal(
+        "a14f8d85a7158fe16b74c6d03b15de27ff62d1baa27704326275e1719ba28b69"
+      );
+      expect(keccak256(message)).to.equal(
+        "0xa63b24aad2153204f26b847f7b009e2f554ebbe512af21b0b9e1c5fa98189d59"
+      );
+    });
+
+    it("rejects SafeTx approvals for a batch", async () => {
+      const { executeParams, remainingAccounts } = await createTransferParams(
+        { safeTx: {} }
+      );
+      const instruction = {
+        programId: executeParams.programId,
+        accounts: executeParams.accounts,
+        data: executeParams.data,
+      };
+
+      try {
+        await program.methods
+          .executeBatch({
+            instructions: [instruction, instruction],
+            signers: executeParams.signers,
+            nonce: executeParams.nonce,
+            ...openWindow,
+            precompileIxIndices: [0],
+            vaultIndex: 0,
+            signingMode: { safeTx: {} },
+          })
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .remainingAccounts(remainingAccounts)
+          .preInstructions([await safeTxIx(executeParams)])
+          .signers([])
+          .rpc();
+
+        expect.fail("should have rejected a batch in SafeTx mode");
+      } catch (e) {
+        expect(e.toString()).to.include("UnsupportedSigningMode");
+      }
+    });
+
+    it("rejects a validity window SafeTx approvals do not sign", async () => {
+      const { executeParams, remainingAccounts } = await createTransferParams(
+        { safeTx: {} }
+      );
+      const windowed = {
+        ...executeParams,
+        validUntil: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+      };
+
+      try {
+        await program.methods
+          .execute(windowed)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .remainingAccounts(remainingAccounts)
+          .preInstructions([await safeTxIx(windowed)])
+          .signers([])
+          .rpc();
+
+        expect.fail("should have rejected a window in SafeTx mode");
+      } catch (e) {
+        expect(e.toString()).to.include("UnsignedWindow");
+      }
+    });
+
+    it("executes with SafeTx approvals", async () => {
+      const balanceBefore = await provider.connection.getBalance(recipient);
+      const { executeParams, remainingAccounts } = await createTransferParams(
+        { safeTx: {} }
+      );
+
+      await program.methods
+        .execute(executeParams)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .remainingAccounts(remainingAccounts)
+        .preInstructions([await safeTxIx(executeParams)])
+        .signers([])
+        .rpc({ commitment: "confirmed" });
+
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        balanceBefore + anchor.web3.LAMPORTS_PER_SOL / 2
+      );
+    });
+  });
+
+  describe("cancel", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+    });
+
+    async function pendingNonce() {
+      const config = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      return config.nonce;
+    }
+
+    async function cancel(
+      nonce: anchor.BN,
+      signingMode: object,
+      secp256k1Ix: TransactionInstruction
+    ) {
+      return program.methods
+        .cancel({
+          vaultIndex: 0,
+          signers: [owner2, owner3].map((wallet) =>
+            Buffer.from(wallet.address.slice(2), "hex")
+          ),
+          nonce,
+          precompileIxIndices: [0],
+          signingMode,
+        })
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .preInstructions([secp256k1Ix])
+        .signers([])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    function rawIx(message: Buffer) {
+      return BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction(
+        [owner2, owner3].map((wallet) => ({
+          privateKey: Buffer.from(wallet.privateKey.slice(2), "hex"),
+          message,
+        }))
+      );
+    }
+
+    it("matches the cancel vectors pinned in the program", () => {
+      const vectorDomain = {
+        programId: new PublicKey(Buffer.alloc(32, 1)),
+        chainId: Buffer.alloc(32, 2),
+        version: TX_HASH_V2,
+        evmChainId: 1,
+      };
+      const vectorPda = new PublicKey(Buffer.alloc(32, 3));
+      const nonce = new anchor.BN(5);
+
+      expect(
+        createCancelHash(vectorDomain, vectorPda, nonce).toString("hex")
+      ).to.equal(
+        "7f57be21f354472824be98733625226e5f057f0e7c531a4478bcc33f9bdb7290"
+      );
+      expect(
+        keccak256(
+          eip712CancelMessage(vectorDomain, eip712CancelValue(vectorPda, nonce))
+        )
+      ).to.equal(
+        "0x4bbccf82cd421f5972e75ce6bd52b657a3c018a1dd44163df534af909e56403b"
+      );
+      expect(
+        keccak256(
+          safeTxMessage(
+            vectorDomain,
+            vectorPda,
+            safeRejectionValue(vectorPda, nonce)
+          )
+        )
+      ).to.equal(
+        "0xe50923dbc433d09bf6ce162d9ccc623098e42305f0396a6bc3ace0f23fff9f1e"
+      );
+    });
+
+    it("burns the pending nonce and records it in an event", async () => {
+      const nonce = await pendingNonce();
+
+      const signature = await cancel(
+        nonce,
+        { raw: {} },
+        await rawIx(createCancelHash(domain, multisigPda, nonce))
+      );
+
+      expect((await pendingNonce()).toString()).to.equal(
+        nonce.addn(1).toString()
+      );
+
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const events = parseCpiEvents(program, tx);
+      expect(events.map((event) => event.name)).to.deep.equal([
+        "nonceCancelled",
+      ]);
+      expect(events[0].data.nonce.toString()).to.equal(nonce.toString());
+    });
+
+    it("rejects transaction approvals as a cancel", async () => {
+      const nonce = await pendingNonce();
+      const txHash = createMultiSigBatchTxHash(
+        domain,
+        multisigPda,
+        nonce,
+        openWindow.validAfter,
+        openWindow.validUntil,
+        []
+      );
+
+      try {
+        await cancel(nonce, { raw: {} }, await rawIx(txHash));
+        expect.fail("should have rejected a transaction hash");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidMessage");
+      }
+    });
+
+    it("cancels with a Safe rejection", async () => {
+      const nonce = await pendingNonce();
+      const value = safeRejectionValue(multisigPda, nonce);
+
+      const params = [];
+      for (const wallet of [owner2, owner3]) {
+        const signature = Signature.from(
+          await wallet.signTypedData(
+            safeTxDomain(domain, multisigPda),
+            SAFE_TX_TYPES,
+            value
+          )
+        );
+        params.push({
+          ethAddress: wallet.address,
+          message: safeTxMessage(domain, multisigPda, value),
+          signature: Buffer.concat([
+            Buffer.from(signature.r.slice(2), "hex"),
+            Buffer.from(signature.s.slice(2), "hex"),
+          ]),
+          recoveryId: signature.yParity,
+        });
+      }
+
+      await cancel(
+        nonce,
+        { safeTx: {} },
+        BatchSecp256k1Signer.createVerifySignaturesInstruction(params)
+      );
+
+      expect((await pendingNonce()).toString()).to.equal(
+        nonce.addn(1).toString()
+      );
+    });
+  });
+
+  describe("nonce lanes", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    const recipient = anchor.web3.Keypair.generate().publicKey;
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+
+      for (const lane of [1, 2]) {
+        await program.methods
+          .openLane(lane)
+          .accounts({
+            config: configAccount,
+            nonceLane: findNonceLanePda(program.programId, configAccount, lane),
+            payer: provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc({ commitment: "confirmed" });
+      }
+    });
+
+    async function executeTransfer(nonce: anchor.BN, lane: number | null) {
+      const transferIx = SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: recipient,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 10,
+      });
+
+      await executeSigned(configAccount, transferIx, {
+        nonce,
+        nonceLane: lane ?? undefined,
+      });
+    }
+
+    async function laneNonceOf(lane: number) {
+      const account = await program.account.nonceLane.fetch(
+        findNonceLanePda(program.programId, configAccount, lane)
+      );
+      return account.nonce.toString();
+    }
+
+    it("executes lanes independently of each other", async () => {
+      // lane 0 has nothing executed, later lanes do not wait for it or each other
+      await executeTransfer(laneNonce(2), 2);
+      await executeTransfer(laneNonce(1), 1);
+      await executeTransfer(laneNonce(2, 1), 2);
+
+      expect(await laneNonceOf(1)).to.equal(laneNonce(1, 1).toString());
+      expect(await laneNonceOf(2)).to.equal(laneNonce(2, 2).toString());
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.nonce.toString()).to.equal("0");
+
+      await executeTransfer(laneNonce(0), null);
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        (4 * anchor.web3.LAMPORTS_PER_SOL) / 10
+      );
+    });
+
+    it("rejects a replay within a lane", async () => {
+      try {
+        await executeTransfer(laneNonce(1), 1);
+        expect.fail("should have rejected a used nonce");
+      } catch (e) {
+        expect(e.toString()).to.include("ErrNonceTooOld");
+      }
+    });
+
+    it("rejects the account of another lane", async () => {
+      try {
+        await executeTransfer(laneNonce(1, 1), 2);
+        expect.fail("should have rejected a mismatched lane account");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidNonceLane");
+      }
+    });
+
+    it("rejects a lane nonce without its lane account", async () => {
+      try {
+        await executeTransfer(laneNonce(1, 1), null);
+        expect.fail("should have required the lane account");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidNonceLane");
+      }
+    });
+
+    it("does not open lane 0", async () => {
+      try {
+        await program.methods
+          .openLane(0)
+          .accounts({
+            config: configAccount,
+            nonceLane: findNonceLanePda(program.programId, configAccount, 0),
+            payer: provider.wallet.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .rpc();
+        expect.fail("should have rejected lane 0");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidNonceLane");
+      }
+    });
+  });
+
+  describe("guard", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    const guard = (anchor.workspace.MockGuard as Program<MockGuard>).programId;
+    // MAX_REFUND and MIN_BALANCE of the mock guard
+    const maxRefund = 10_000;
+    const minBalance = anchor.web3.LAMPORTS_PER_SOL;
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+    });
+
+    const setGuardIx = (guard: PublicKey | null) =>
+      program.methods
+        .setGuard(guard)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+    const transferIx = (lamports = anchor.web3.LAMPORTS_PER_SOL / 10) =>
+      SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: anchor.web3.Keypair.generate().publicKey,
+        lamports,
+      });
+
+    it("rejects the multisig program as its own guard", async () => {
+      try {
+        await executeSigned(configAccount, await setGuardIx(program.programId));
+        expect.fail("should have rejected the multisig as guard");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidGuard");
+      }
+    });
+
+    it("sets the guard through the multisig", async () => {
+      await executeSigned(configAccount, await setGuardIx(guard));
+
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.guard.toString()).to.equal(guard.toString());
+    });
+
+    it("requires the guard account once a guard is set", async () => {
+      try {
+        await executeSigned(configAccount, transferIx());
+        expect.fail("should have required the guard account");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidGuard");
+      }
+    });
+
+    it("runs both hooks around the transaction", async () => {
+      const { nonce } = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+
+      const { signature } = await executeSigned(configAccount, transferIx(), {
+        guard,
+      });
+
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const logged = (hook: string) =>
+        tx.meta.logMessages.indexOf(`Program log: ${hook} of nonce ${nonce}`);
+      expect(logged("check_transaction")).to.not.equal(-1);
+      expect(logged("check_after_execution")).to.be.greaterThan(
+        logged("check_transaction")
+      );
+    });
+
+    it("aborts the transaction when check_transaction fails", async () => {
+      const { nonce } = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      const balanceBefore = await provider.connection.getBalance(multisigPda);
+      const receiver = anchor.web3.Keypair.generate().publicKey;
+      const refund = {
+        amount: new anchor.BN(maxRefund + 1),
+        mint: PublicKey.default,
+        receiver,
+      };
+
+      try {
+        await executeSigned(configAccount, transferIx(), {
+          guard,
+          refund,
+          refundReceiver: receiver,
+        });
+        expect.fail("should have been vetoed by the guard");
+      } catch (e) {
+        expect(e.toString()).to.include("RefundTooHigh");
+      }
+
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.nonce.toString()).to.equal(nonce.toString());
+      expect(await provider.connection.getBalance(multisigPda)).to.equal(
+        balanceBefore
+      );
+    });
+
+    it("aborts the transaction when check_after_execution fails", async () => {
+      const { nonce } = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      const balanceBefore = await provider.connection.getBalance(multisigPda);
+
+      // leaves the pda one lamport short of the guard's minimum
+      try {
+        await executeSigned(
+          configAccount,
+          transferIx(balanceBefore - minBalance + 1),
+          { guard }
+        );
+        expect.fail("should have been vetoed by the guard");
+      } catch (e) {
+        expect(e.toString()).to.include("BalanceTooLow");
+      }
+
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.nonce.toString()).to.equal(nonce.toString());
+      expect(await provider.connection.getBalance(multisigPda)).to.equal(
+        balanceBefore
+      );
+    });
+  });
+
+  describe("modules", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    // stands in for the pda a module program signs with
+    const module = anchor.web3.Keypair.generate();
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+    });
+
+    async function executeFromModule(
+      signer: anchor.web3.Keypair,
+      recipient: PublicKey
+    ) {
+      const transferIx = SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: recipient,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 10,
+      });
+      const accounts = transferIx.keys.map((key) => ({
+        pubkey: key.pubkey,
+        isSigner: key.pubkey.equals(multisigPda) ? false : key.isSigner,
+        isWritable: key.isWritable,
+      }));
+
+      return program.methods
+        .executeFromModule([
+          {
+            programId: transferIx.programId,
+            accounts,
+            data: transferIx.data,
+          },
+        ])
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+          module: signer.publicKey,
+        })
+        .remainingAccounts([
+          ...accounts,
+          {
+            pubkey: SystemProgram.programId,
+            isSigner: false,
+            isWritable: false,
+          },
+        ])
+        .signers([signer])
+        .rpc({ commitment: "confirmed" });
+    }
+
+    it("rejects a module that is not enabled", async () => {
+      try {
+        await executeFromModule(
+          module,
+          anchor.web3.Keypair.generate().publicKey
+        );
+        expect.fail("should have rejected an unknown module");
+      } catch (e) {
+        expect(e.toString()).to.include("ModuleNotEnabled");
+      }
+    });
+
+    it("executes from an enabled module without approvals", async () => {
+      await executeSigned(
+        configAccount,
+        await program.methods
+          .enableModule(module.publicKey)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .instruction()
+      );
+
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.modules.map((key) => key.toString())).to.deep.equal([
+        module.publicKey.toString(),
+      ]);
+
+      const recipient = anchor.web3.Keypair.generate().publicKey;
+      const signature = await executeFromModule(module, recipient);
+
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        anchor.web3.LAMPORTS_PER_SOL / 10
+      );
+      // no approvals, so the event is all an indexer sees of the execution
+      const events = parseCpiEvents(
+        program,
+        await provider.connection.getTransaction(signature, {
+          commitment: "confirmed",
+          maxSupportedTransactionVersion: 0,
+        })
+      );
+      expect(events.map((event) => event.name)).to.deep.equal([
+        "moduleTransactionExecuted",
+      ]);
+      expect(events[0].data.config.toString()).to.equal(
+        configAccount.toString()
+      );
+      expect(events[0].data.module.toString()).to.equal(
+        module.publicKey.toString()
+      );
+      expect(events[0].data.instructionCount).to.equal(1);
+      // module calls do not use up a nonce
+      const after = await program.account.multiSigConfig.fetch(configAccount);
+      expect(after.nonce.toString()).to.equal(config.nonce.toString());
+    });
+
+    it("rejects a module after it was disabled", async () => {
+      await executeSigned(
+        configAccount,
+        await program.methods
+          .disableModule(module.publicKey)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .instruction()
+      );
+
+      try {
+        await executeFromModule(
+          module,
+          anchor.web3.Keypair.generate().publicKey
+        );
+        expect.fail("should have rejected a disabled module");
+      } catch (e) {
+        expect(e.toString()).to.include("ModuleNotEnabled");
+      }
+    });
+  });
+
+  describe("allowances", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    const delegate = anchor.web3.Keypair.generate();
+    let allowance: PublicKey;
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+
+      allowance = findAllowancePda(
+        program.programId,
+        configAccount,
+        delegate.publicKey
+      );
+    });
+
+    async function spend(signer: anchor.web3.Keypair, lamports: number) {
+      const recipient = anchor.web3.Keypair.generate().publicKey;
+
+      const signature = await program.methods
+        .spendAllowance(new anchor.BN(lamports))
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+          allowance,
+          delegate: signer.publicKey,
+          recipient,
+          vault: null,
+          mint: null,
+          tokenProgram: null,
+        })
+        .signers([signer])
+        .rpc({ commitment: "confirmed" });
+
+      return { recipient, signature };
+    }
+
+    it("grants a SOL allowance through the multisig", async () => {
+      await executeSigned(
+        configAccount,
+        await program.methods
+          .setAllowance(
+            delegate.publicKey,
+            PublicKey.default,
+            new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 5),
+            new anchor.BN(3600)
+          )
+          .accounts({
+            config: configAccount,
+            allowance,
+            multisigPda: multisigPda,
+          })
+          .instruction()
+      );
+
+      const account = await program.account.allowance.fetch(allowance);
+      expect(account.delegate.toString()).to.equal(
+        delegate.publicKey.toString()
+      );
+      expect(account.amount.toString()).to.equal(
+        (anchor.web3.LAMPORTS_PER_SOL / 5).toString()
+      );
+      expect(account.spent.toString()).to.equal("0");
+    });
+
+    it("lets the delegate spend within the allowance", async () => {
+      const { recipient, signature } = await spend(
+        delegate,
+        anchor.web3.LAMPORTS_PER_SOL / 10
+      );
+
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        anchor.web3.LAMPORTS_PER_SOL / 10
+      );
+      const account = await program.account.allowance.fetch(allowance);
+      expect(account.spent.toString()).to.equal(
+        (anchor.web3.LAMPORTS_PER_SOL / 10).toString()
+      );
+
+      const events = parseCpiEvents(
+        program,
+        await provider.connection.getTransaction(signature, {
+          commitment: "confirmed",
+          maxSupportedTransactionVersion: 0,
+        })
+      );
+      expect(events.map((event) => event.name)).to.deep.equal([
+        "allowanceSpent",
+      ]);
+      expect(events[0].data.delegate.toString()).to.equal(
+        delegate.publicKey.toString()
+      );
+      expect(events[0].data.recipient.toString()).to.equal(
+        recipient.toString()
+      );
+    });
+
+    it("rejects spending beyond the remaining allowance", async () => {
+      try {
+        await spend(delegate, (anchor.web3.LAMPORTS_PER_SOL * 3) / 20);
+        expect.fail("should have rejected an amount above the allowance");
+      } catch (e) {
+        expect(e.toString()).to.include("AllowanceExceeded");
+      }
+    });
+
+    it("rejects anyone but the delegate", async () => {
+      try {
+        await spend(anchor.web3.Keypair.generate(), 1);
+        expect.fail("should have rejected another signer");
+      } catch (e) {
+        expect(e.toString()).to.include("ConstraintHasOne");
+      }
+    });
+
+    it("removes the allowance through the multisig", async () => {
+      await executeSigned(
+        configAccount,
+        await program.methods
+          .removeAllowance()
+          .accounts({
+            config: configAccount,
+            allowance,
+            multisigPda: multisigPda,
+          })
+          .instruction()
+      );
+
+      expect(await provider.connection.getAccountInfo(allowance)).to.equal(
+        null
+      );
+    });
+  });
+
+  describe("events", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+
+    async function eventsOf(signature: string) {
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return parseCpiEvents(program, tx);
+    }
+
+    it("emits the created multisig", async () => {
+      let signature: string;
+      ({ configAccount, multisigPda, signature } = await createConfig());
+
+      const events = await eventsOf(signature);
+      expect(events.map((event) => event.name)).to.deep.equal([
+        "multisigCreated",
+      ]);
+      expect(events[0].data.config.toString()).to.equal(
+        configAccount.toString()
+      );
+      expect(events[0].data.multisigPda.toString()).to.equal(
+        multisigPda.toString()
+      );
+      expect(events[0].data.owners).to.have.length(3);
+      expect(events[0].data.threshold).to.equal(2);
+    });
+
+    it("emits the config change and the execution", async () => {
+      const ix = await program.methods
+        .changeThreshold(3)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      const { signature, txHash } = await executeSigned(configAccount, ix);
+
+      // the config change is emitted inside the execution, before it completes
+      const events = await eventsOf(signature);
+      expect(events.map((event) => event.name)).to.deep.equal([
+        "thresholdChanged",
+        "transactionExecuted",
+      ]);
+      expect(events[0].data.threshold).to.equal(3);
+
+      const executed = events[1].data;
+      expect(Buffer.from(executed.txHash).toString("hex")).to.equal(
+        txHash.toString("hex")
+      );
+      expect(executed.nonce.toString()).to.equal("0");
+      expect(
+        executed.signers.map((signer) => Buffer.from(signer).toString("hex"))
+      ).to.deep.equal(
+        [owner2, owner3].map((wallet) => wallet.address.slice(2).toLowerCase())
+      );
+      expect(executed.programs.map(String)).to.deep.equal([
+        program.programId.toString(),
+      ]);
+      expect(executed.refund).to.be.null;
+    });
+  });
+
+  describe("refunds", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    const receiver = anchor.web3.Keypair.generate().publicKey;
+    // the receiver is a new account, so the refund has to cover its rent
+    const amount = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 100);
+    const transferred = 1000;
+
+    const vectorDomain = {
+      programId: new PublicKey(Buffer.alloc(32, 1)),
+      chainId: Buffer.alloc(32, 2),
+      version: TX_HASH_V2,
+      evmChainId: 1,
+    };
+    const vectorPda = new PublicKey(Buffer.alloc(32, 3));
+    const vectorRefund = {
+      amount: new anchor.BN(5000),
+      mint: PublicKey.default,
+      receiver: new PublicKey(Buffer.alloc(32, 7)),
+    };
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+    });
+
+    // a small transfer to the relayer, approved with `signedRefund` and executed with `refund`
+    async function executeWithRefund(
+      signedRefund: Refund | null,
+      refund: Refund,
+      refundReceiver: PublicKey
+    ) {
+      const transferIx = SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: provider.wallet.publicKey,
+        lamports: transferred,
+      });
+
+      const { signature } = await executeSigned(configAccount, transferIx, {
+        refund,
+        signedRefund,
+        refundReceiver,
+      });
+      return signature;
+    }
+
+    it("matches the refund vector pinned in the program", () => {
+      const txHash = createMultiSigTxHash(
+        vectorDomain,
+        vectorPda,
+        new anchor.BN(5),
+        openWindow.validAfter,
+        openWindow.validUntil,
+        [
+          {
+            pubkey: new PublicKey(Buffer.alloc(32, 7)),
+            isSigner: true,
+            isWritable: false,
+          },
+        ],
+        Buffer.from([0xaa, 0xbb]),
+        new PublicKey(Buffer.alloc(32, 8)),
+        vectorRefund
+      );
+
+      expect(txHash.toString("hex")).to.equal(
+        "ccc0799bc4f2ef6eecdb4f03b3ad98ef793ce1a07ebe47aec259712853fa863b"
+      );
+    });
+
+    it("matches the typed data refund vectors pinned in the program", () => {
+      const instruction = {
+        programId: new PublicKey(Buffer.alloc(32, 8)),
+        accounts: [
+          {
+            pubkey: new PublicKey(Buffer.alloc(32, 7)),
+            isSigner: true,
+            isWritable: false,
+          },
+        ],
+        data: Buffer.from([0xaa, 0xbb]),
+      };
+      const eip712 = eip712Message(
+        vectorDomain,
+        eip712Value(
+          vectorPda,
+          new anchor.BN(5),
+          openWindow.validAfter,
+          openWindow.validUntil,
+          [instruction],
+          vectorRefund
+        )
+      );
+      expect(keccak256(eip712)).to.equal(
+        "0xe54e03b3710ca22214b26da08d61c5933d4ad26a6a31cd536277b12447511345"
+      );
+
+      const safeTx = safeTxMessage(
+        vectorDomain,
+        vectorPda,
+        safeTxValue(new anchor.BN(5), instruction, vectorRefund)
+      );
+      expect(keccak256(safeTx)).to.equal(
+        "0xecb9f2b5f0db2750651a728ff2b661b51a764a1b673f715e6e59d1af7eeb9496"
+      );
+    });
+
+    it("pays the signed refund to its receiver", async () => {
+      const refund = { amount, mint: PublicKey.default, receiver };
+
+      const signature = await executeWithRefund(refund, refund, receiver);
+
+      expect(await provider.connection.getBalance(receiver)).to.equal(
+        amount.toNumber()
+      );
+      const events = parseCpiEvents(
+        program,
+        await provider.connection.getTransaction(signature, {
+          commitment: "confirmed",
+          maxSupportedTransactionVersion: 0,
+        })
+      );
+      const executed = events.find(
+        (event) => event.name === "transactionExecuted"
+      ).data;
+      expect(executed.refund.amount.toString()).to.equal(amount.toString());
+      expect(executed.refund.receiver.toString()).to.equal(
+        receiver.toString()
+      );
+    });
+
+    it("rejects a refund the owners did not sign", async () => {
+      const refund = { amount, mint: PublicKey.default, receiver };
+
+      try {
+        await executeWithRefund(null, refund, receiver);
+        expect.fail("should have rejected an unsigned refund");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidMessage");
+      }
+    });
+
+    it("rejects a refund paid to another account", async () => {
+      const refund = { amount, mint: PublicKey.default, receiver };
+
+      try {
+        await executeWithRefund(refund, refund, provider.wallet.publicKey);
+        expect.fail("should have rejected another receiver");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidRefund");
+      }
+    });
+
+    it("refunds the relayer when no receiver is signed", async () => {
+      const refund = {
+        amount,
+        mint: PublicKey.default,
+        receiver: PublicKey.default,
+      };
+      // the fee payer relays the transaction
+      const relayer = provider.wallet.publicKey;
+      const balanceBefore = await provider.connection.getBalance(relayer);
+
+      const signature = await executeWithRefund(refund, refund, relayer);
+
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      expect(await provider.connection.getBalance(relayer)).to.equal(
+        balanceBefore + amount.toNumber() + transferred - tx.meta.fee
+      );
+    });
+  });
+
+  describe("vaults", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig());
+    });
+
+    // executes `ix` from vault `index`, approved by `signers` for the pda of `signedIndex`
+    const executeInVault = (
+      index: number,
+      ix: TransactionInstruction,
+      signers: Wallet[],
+      signedIndex: number = index
+    ) =>
+      executeSigned(configAccount, ix, {
+        signers,
+        vaultIndex: index,
+        signedIndex,
+      });
+
+    async function addVault(index: number, owners: Wallet[]) {
+      await executeInVault(
+        0,
+        await program.methods
+          .addVault(
+            index,
+            owners.map((owner) => Buffer.from(owner.address.slice(2), "hex")),
+            owners.length
+          )
+          .accounts({
+            config: configAccount,
+            vault: findVaultPda(program.programId, configAccount, index),
+            multisigPda: multisigPda,
+          })
+          .instruction(),
+        [owner2, owner3]
+      );
+      await airdropSol(
+        findVaultSignerPda(program.programId, configAccount, index)
+      );
+    }
+
+    const transferFrom = (index: number, recipient: PublicKey) =>
+      SystemProgram.transfer({
+        fromPubkey: findVaultSignerPda(program.programId, configAccount, index),
+        toPubkey: recipient,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 10,
+      });
+
+    it("adds vaults through the multisig", async () => {
+      await addVault(1, [owner2, owner3]);
+      await addVault(2, [owner3]);
+
+      const vault = await program.account.vault.fetch(
+        findVaultPda(program.programId, configAccount, 1)
+      );
+      expect(vault.index).to.equal(1);
+      expect(vault.threshold).to.equal(2);
+      expect(
+        vault.owners.map((owner) => "0x" + Buffer.from(owner).toString("hex"))
+      ).to.deep.equal(
+        [owner2, owner3].map((owner) => owner.address.toLowerCase())
+      );
+    });
+
+    it("signs with the pda of the vault", async () => {
+      const recipient = anchor.web3.Keypair.generate().publicKey;
+
+      await executeInVault(1, transferFrom(1, recipient), [owner2, owner3]);
+
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        anchor.web3.LAMPORTS_PER_SOL / 10
+      );
+    });
+
+    it("executes with the threshold of the vault", async () => {
+      const recipient = anchor.web3.Keypair.generate().publicKey;
+
+      await executeInVault(2, transferFrom(2, recipient), [owner3]);
+
+      expect(await provider.connection.getBalance(recipient)).to.equal(
+        anchor.web3.LAMPORTS_PER_SOL / 10
+      );
+    });
+
+    it("rejects config owners outside the vault", async () => {
+      try {
+        await executeInVault(
+          2,
+          transferFrom(2, anchor.web3.Keypair.generate().publicKey),
+          [owner2, owner3]
+        );
+        expect.fail("should have rejected an owner outside the vault");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidSigner");
+      }
+    });
+
+    it("rejects approvals for another vault", async () => {
+      try {
+        await executeInVault(
+          1,
+          transferFrom(1, anchor.web3.Keypair.generate().publicKey),
+          [owner2, owner3],
+          0
+        );
+        expect.fail("should have rejected approvals for the multisig pda");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidMessage");
+      }
+    });
+
+    it("counts the nonces of each vault", async () => {
+      const vault = findVaultPda(program.programId, configAccount, 1);
+      const configBefore = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      const vaultBefore = await program.account.vault.fetch(vault);
+
+      await executeInVault(
+        1,
+        transferFrom(1, anchor.web3.Keypair.generate().publicKey),
+        [owner2, owner3]
+      );
+
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.nonce.toString()).to.equal(configBefore.nonce.toString());
+      const { nonce } = await program.account.vault.fetch(vault);
+      expect(nonce.toString()).to.equal(vaultBefore.nonce.addn(1).toString());
+    });
+
+    it("cancels the pending nonce of a vault", async () => {
+      const vault = findVaultPda(program.programId, configAccount, 1);
+      const vaultPda = findVaultSignerPda(program.programId, configAccount, 1);
+      const configBefore = await program.account.multiSigConfig.fetch(
+        configAccount
+      );
+      const { nonce } = await program.account.vault.fetch(vault);
+      const message = createCancelHash(domain, vaultPda, nonce);
+
+      await program.methods
+        .cancel({
+          vaultIndex: 1,
+          signers: [owner2, owner3].map((wallet) =>
+            Buffer.from(wallet.address.slice(2), "hex")
+          ),
+          nonce,
+          precompileIxIndices: [0],
+          signingMode: { raw: {} },
+        })
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+          vault,
+          vaultPda,
+        })
+        .preInstructions([
+          BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction(
+            [owner2, owner3].map((wallet) => ({
+              privateKey: Buffer.from(wallet.privateKey.slice(2), "hex"),
+              message,
+            }))
+          ),
+        ])
+        .rpc({ commitment: "confirmed" });
+
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.nonce.toString()).to.equal(configBefore.nonce.toString());
+      const account = await program.account.vault.fetch(vault);
+      expect(account.nonce.toString()).to.equal(nonce.addn(1).toString());
+
+      try {
+        await executeSigned(
+          configAccount,
+          transferFrom(1, anchor.web3.Keypair.generate().publicKey),
+          { signers: [owner2, owner3], vaultIndex: 1, nonce }
+        );
+        expect.fail("should have rejected the cancelled proposal");
+      } catch (e) {
+        expect(e.toString()).to.include("ErrNonceTooOld");
+      }
+    });
+
+    it("needs the threshold of a policy in a vault", async () => {
+      // the system program's instructions start with their index as a u32
+      const transferPrefix = Buffer.from([2, 0, 0, 0]);
+      await executeInVault(
+        0,
+        await program.methods
+          .addPolicy(SystemProgram.programId, transferPrefix, 2)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .instruction(),
+        [owner2, owner3]
+      );
+
+      try {
+        await executeInVault(
+          2,
+          transferFrom(2, anchor.web3.Keypair.generate().publicKey),
+          [owner3]
+        );
+        expect.fail("should have required the threshold of the policy");
+      } catch (e) {
+        expect(e.toString()).to.include("ThresholdNotMet");
+      }
+
+      await executeInVault(
+        0,
+        await program.methods
+          .removePolicy(SystemProgram.programId, transferPrefix)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .instruction(),
+        [owner2, owner3]
+      );
+    });
+
+    it("checks every vault when owners change", async () => {
+      const removeOwner = (vaults: number[]) =>
+        program.methods
+          .removeOwner(Buffer.from(owner2.address.slice(2), "hex"), 2)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+            systemProgram: SystemProgram.programId,
+          })
+          .remainingAccounts(
+            vaults.map((index) => ({
+              pubkey: findVaultPda(program.programId, configAccount, index),
+              isSigner: false,
+              isWritable: false,
+            }))
+          )
+          .instruction();
+
+      try {
+        await executeInVault(0, await removeOwner([2]), [owner2, owner3]);
+        expect.fail("should have required every vault");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidVault");
+      }
+
+      // vault 1 would be left with owner3 alone, short of its threshold
+      try {
+        await executeInVault(0, await removeOwner([1, 2]), [owner2, owner3]);
+        expect.fail("should have kept the owner of vault 1");
+      } catch (e) {
+        expect(e.toString()).to.include("OwnerNotFound");
+      }
+    });
+
+    it("checks every vault when an owner is swapped", async () => {
+      const swapOwner = await program.methods
+        .swapOwner(
+          Buffer.from(owner3.address.slice(2), "hex"),
+          Buffer.from(Wallet.createRandom().address.slice(2), "hex")
+        )
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .remainingAccounts(
+          [1, 2].map((index) => ({
+            pubkey: findVaultPda(program.programId, configAccount, index),
+            isSigner: false,
+            isWritable: false,
+          }))
+        )
+        .instruction();
+
+      // the vaults list owner3, the new owner would not be one of theirs
+      try {
+        await executeInVault(0, swapOwner, [owner2, owner3]);
+        expect.fail("should have kept the owner of the vaults");
+      } catch (e) {
+        expect(e.toString()).to.include("OwnerNotFound");
+      }
+    });
+
+    it("removes a vault through the multisig", async () => {
+      const vault = findVaultPda(program.programId, configAccount, 2);
+
+      await executeInVault(
+        0,
+        await program.methods
+          .removeVault()
+          .accounts({
+            config: configAccount,
+            vault,
+            multisigPda: multisigPda,
+          })
+          .instruction(),
+        [owner2, owner3]
+      );
+
+      expect(await program.account.vault.fetchNullable(vault)).to.be.null;
+    });
+
+    it("does not reuse the nonces of a removed vault", async () => {
+      await addVault(2, [owner3]);
+
+      const vault = await program.account.vault.fetch(
+        findVaultPda(program.programId, configAccount, 2)
+      );
+      // the third vault added to the config starts the third generation
+      expect(vault.nonce.toString()).to.equal(
+        new anchor.BN(2).shln(32).toString()
+      );
+    });
+
+    it("can not close the config while it has vaults", async () => {
+      const close = await program.methods
+        .close()
+        .accounts({
+          config: configAccount,
+          receiver: provider.wallet.publicKey,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      try {
+        await executeInVault(0, close, [owner2, owner3]);
+        expect.fail("should have kept the config of the vaults");
+      } catch (e) {
+        expect(e.toString()).to.include("VaultsRemaining");
+      }
+    });
+  });
+
+  describe("config pdas", () => {
+    const createKey = anchor.web3.Keypair.generate().publicKey;
+    let configAccount: PublicKey;
+    let multisigPda: PublicKey;
+
+    before(() => {
+      configAccount = findConfigPda(
+        program.programId,
+        provider.wallet.publicKey,
+        createKey
+      );
+      multisigPda = findVaultSignerPda(program.programId, configAccount, 0);
+    });
+
+    const createWithKey = () =>
+      program.methods
+        .createWithKey(
+          createKey,
+          [owner1, owner2, owner3].map((wallet) =>
+            Buffer.from(wallet.address.slice(2), "hex")
+          ),
+          [1, 1, 1],
+          2,
+          domain.chainId,
+          new anchor.BN(domain.evmChainId),
+          domain.version
+        )
+        .accounts({
+          config: configAccount,
+          creator: provider.wallet.publicKey,
+          payer: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc({ commitment: "confirmed" });
+
+    it("creates the config at the pda of its creator and key", async () => {
+      await createWithKey();
+
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.owners.length).to.equal(3);
+      expect(config.multisigPda.toString()).to.equal(multisigPda.toString());
+    });
+
+    it("closes the config through the multisig", async () => {
+      await airdropSol(multisigPda);
+      const receiver = anchor.web3.Keypair.generate().publicKey;
+      const rent = await provider.connection.getBalance(configAccount);
+
+      const ix = await program.methods
+        .close()
+        .accounts({
+          config: configAccount,
+          receiver,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+      await executeSigned(configAccount, ix);
+
+      expect(await provider.connection.getAccountInfo(configAccount)).to.be
+        .null;
+      expect(await provider.connection.getBalance(receiver)).to.equal(rent);
+    });
+
+    it("can not create a closed config again", async () => {
+      try {
+        await createWithKey();
+        expect.fail("should have rejected a closed config");
+      } catch (e) {
+        expect(e.toString()).to.include("ConfigClosed");
+      }
+    });
+  });
+
+  describe("roles", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    const owner4 = Wallet.createRandom();
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig({
+        owners: [owner2, owner3, owner4],
+      }));
+    });
+
+    // executes `ix` approved by `signers`, submitted by `executor` if there is one
+    const executeWith = (
+      ix: TransactionInstruction,
+      signers: Wallet[],
+      executor?: anchor.web3.Keypair
+    ) => executeSigned(configAccount, ix, { signers, executor });
+
+    const setRoles = (owner: Wallet, roles: number) =>
+      program.methods
+        .setRoles(Buffer.from(owner.address.slice(2), "hex"), roles)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+    const transfer = () =>
+      SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: anchor.web3.Keypair.generate().publicKey,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 10,
+      });
+
+    it("counts only voters towards the threshold", async () => {
+      await executeWith(await setRoles(owner4, ROLE_PROPOSER), [
+        owner2,
+        owner3,
+      ]);
+
+      try {
+        await executeWith(transfer(), [owner2, owner4]);
+        expect.fail("should have ignored the weight of a proposer");
+      } catch (e) {
+        expect(e.toString()).to.include("ThresholdNotMet");
+      }
+    });
+
+    it("requires the approval of a proposer", async () => {
+      await executeWith(await setRoles(owner3, ROLE_VOTER), [
+        owner2,
+        owner3,
+      ]);
+      await executeWith(await setRoles(owner2, ROLE_VOTER), [
+        owner2,
+        owner3,
+      ]);
+
+      try {
+        await executeWith(transfer(), [owner2, owner3]);
+        expect.fail("should have rejected a transaction without a proposer");
+      } catch (e) {
+        expect(e.toString()).to.include("MissingRole");
+      }
+
+      await executeWith(transfer(), [owner2, owner3, owner4]);
+    });
+
+    it("requires the approval of a canceller", async () => {
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      const message = createCancelHash(domain, multisigPda, config.nonce);
+      const secp256k1Ix =
+        BatchSecp256k1Signer.signAndCreateVerifySignaturesInstruction(
+          [owner2, owner3].map((wallet) => ({
+            privateKey: Buffer.from(wallet.privateKey.slice(2), "hex"),
+            message,
+          }))
+        );
+
+      try {
+        await program.methods
+          .cancel({
+            vaultIndex: 0,
+            signers: [owner2, owner3].map((wallet) =>
+              Buffer.from(wallet.address.slice(2), "hex")
+            ),
+            nonce: config.nonce,
+            precompileIxIndices: [0],
+            signingMode: { raw: {} },
+          })
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .preInstructions([secp256k1Ix])
+          .rpc({ commitment: "confirmed" });
+        expect.fail("should have rejected a cancel without a canceller");
+      } catch (e) {
+        expect(e.toString()).to.include("MissingRole");
+      }
+    });
+
+    it("only lets executors submit once there are any", async () => {
+      const executor = anchor.web3.Keypair.generate();
+      await executeWith(
+        await program.methods
+          .setExecutors([executor.publicKey])
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .instruction(),
+        [owner2, owner3, owner4]
+      );
+
+      try {
+        await executeWith(transfer(), [owner2, owner3, owner4]);
+        expect.fail("should have rejected a transaction without an executor");
+      } catch (e) {
+        expect(e.toString()).to.include("MissingRole");
+      }
+
+      try {
+        await executeWith(
+          transfer(),
+          [owner2, owner3, owner4],
+          anchor.web3.Keypair.generate()
+        );
+        expect.fail("should have rejected an unknown executor");
+      } catch (e) {
+        expect(e.toString()).to.include("MissingRole");
+      }
+
+      await executeWith(transfer(), [owner2, owner3, owner4], executor);
+    });
+  });
+
+  describe("policies", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    const owner4 = Wallet.createRandom();
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig({
+        owners: [owner2, owner3, owner4],
+      }));
+    });
+
+    const executeWith = (ix: TransactionInstruction, signers: Wallet[]) =>
+      executeSigned(configAccount, ix, { signers });
+
+    const changeThreshold = (threshold: number) =>
+      program.methods
+        .changeThreshold(threshold)
+        .accounts({
+          config: configAccount,
+          multisigPda: multisigPda,
+        })
+        .instruction();
+
+    const transfer = () =>
+      SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: anchor.web3.Keypair.generate().publicKey,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 10,
+      });
+
+    // the system program's instructions start with their index as a u32
+    const transferPrefix = Buffer.from([2, 0, 0, 0]);
+
+    it("needs the threshold of a matching policy", async () => {
+      await executeWith(
+        await program.methods
+          .addPolicy(SystemProgram.programId, transferPrefix, 3)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .instruction(),
+        [owner2, owner3]
+      );
+
+      try {
+        await executeWith(transfer(), [owner2, owner3]);
+        expect.fail("should have required the threshold of the policy");
+      } catch (e) {
+        expect(e.toString()).to.include("ThresholdNotMet");
+      }
+
+      await executeWith(transfer(), [owner2, owner3, owner4]);
+    });
+
+    it("falls back to the threshold after the policy is removed", async () => {
+      await executeWith(
+        await program.methods
+          .removePolicy(SystemProgram.programId, transferPrefix)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .instruction(),
+        [owner2, owner3]
+      );
+
+      await executeWith(transfer(), [owner2, owner3]);
+    });
+
+    it("rejects a policy for the multisig itself", async () => {
+      try {
+        await executeWith(
+          await program.methods
+            .addPolicy(program.programId, Buffer.from([]), 1)
+            .accounts({
+              config: configAccount,
+              multisigPda: multisigPda,
+            })
+            .instruction(),
+          [owner2, owner3]
+        );
+        expect.fail("should have rejected a policy for the multisig");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidPolicy");
+      }
+    });
+
+    it("needs the admin threshold for changes to the config", async () => {
+      await executeWith(
+        await program.methods
+          .changeAdminThreshold(3)
+          .accounts({
+            config: configAccount,
+            multisigPda: multisigPda,
+          })
+          .instruction(),
+        [owner2, owner3]
+      );
+
+      try {
+        await executeWith(await changeThreshold(1), [owner2, owner3]);
+        expect.fail("should have required the admin threshold");
+      } catch (e) {
+        expect(e.toString()).to.include("ThresholdNotMet");
+      }
+
+      await executeWith(await changeThreshold(1), [owner2, owner3, owner4]);
+      // other programs still use the config's threshold
+      await executeWith(transfer(), [owner2]);
+    });
+
+    it("keeps the admin threshold at or above the threshold", async () => {
+      await executeWith(await changeThreshold(2), [owner2, owner3, owner4]);
+
+      try {
+        await executeWith(
+          await program.methods
+            .changeAdminThreshold(1)
+            .accounts({
+              config: configAccount,
+              multisigPda: multisigPda,
+            })
+            .instruction(),
+          [owner2, owner3, owner4]
+        );
+        expect.fail("should have rejected an admin threshold below 2");
+      } catch (e) {
+        expect(e.toString()).to.include("InvalidThreshold");
+      }
+    });
+  });
+
+  describe("reentrancy", () => {
+    let multisigPda: PublicKey;
+    let configAccount: PublicKey;
+    const owner4 = Wallet.createRandom();
+
+    before(async () => {
+      ({ configAccount, multisigPda } = await createConfig({
+        owners: [owner2, owner3, owner4],
+      }));
+    });
+
+    const executeWith = (ix: TransactionInstruction, signers: Wallet[]) =>
+      executeSigned(configAccount, ix, { signers });
+
+    // accounts for the multisig program itself, called with the same config
+    const nestedAccounts = () => ({
+      config: configAccount,
+      multisigPda: multisigPda,
+    });
+
+    const transfer = (configMeta: { isWritable: boolean }) => {
+      const ix = SystemProgram.transfer({
+        fromPubkey: multisigPda,
+        toPubkey: anchor.web3.Keypair.generate().publicKey,
+        lamports: anchor.web3.LAMPORTS_PER_SOL / 10,
+      });
+      ix.keys.push({
+        pubkey: configAccount,
+        isSigner: false,
+        isWritable: configMeta.isWritable,
+      });
+      return ix;
+    };
+
+    it("rejects an execute nested in an execute of the config", async () => {
+      const nested = await program.methods
+        .execute({
+          programId: SystemProgram.programId,
+          accounts: [],
+          data: Buffer.alloc(0),
+          signers: [owner2, owner3].map((wallet) =>
+            Buffer.from(wallet.address.slice(2), "hex")
+          ),
+          nonce: new anchor.BN(1),
+          ...openWindow,
+          precompileIxIndices: [0],
+          vaultIndex: 0,
+          signingMode: { raw: {} },
+        })
+        .accounts(nestedAccounts())
+        .instruction();
+
+      try {
+        await executeWith(nested, [owner2, owner3]);
+        expect.fail("should have rejected a nested execute");
+      } catch (e) {
+        expect(e.toString()).to.include("Reentrancy");
+      }
+    });
+
+    it("rejects a cancel nested in an execute of the config", async () => {
+      const nested = await program.methods
+        .cancel({
+          vaultIndex: 0,
+          signers: [owner2, owner3].map((wallet) =>
+            Buffer.from(wallet.address.slice(2), "hex")
+          ),
+          nonce: new anchor.BN(1),
+          precompileIxIndices: [0],
+          signingMode: { raw: {} },
+        })
+        .accounts(nestedAccounts())
+        .instruction();
+
+      try {
+        await executeWith(nested, [owner2, owner3]);
+        expect.fail("should have rejected a nested cancel");
+      } catch (e) {
+        expect(e.toString()).to.include("Reentrancy");
+      }
+    });
+
+    it("rejects a writable config for another program", async () => {
+      try {
+        await executeWith(transfer({ isWritable: true }), [owner2, owner3]);
+        expect.fail("should have rejected a writable config");
+      } catch (e) {
+        expect(e.toString()).to.include("WritableConfig");
+      }
+    });
+
+    it("passes the config read-only to other programs", async () => {
+      await executeWith(transfer({ isWritable: false }), [owner2, owner3]);
+
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.nonce.toNumber()).to.equal(1);
+    });
+
+    it("clears the flag once the config changed itself", async () => {
+      await executeWith(
+        await program.methods
+          .changeThreshold(3)
+          .accounts(nestedAccounts())
+          .instruction(),
+        [owner2, owner3]
+      );
+
+      const config = await program.account.multiSigConfig.fetch(configAccount);
+      expect(config.threshold).to.equal(3);
+      expect(config.executing).to.equal(false);
+    });
   });
 });
@@ -0,0 +1,483 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Connection, PublicKey } from "@solana/web3.js";
+import { keccak_256 } from "js-sha3";
+import {
+  TypedDataEncoder,
+  ZeroAddress,
+  dataSlice,
+  getAddress,
+  getBytes,
+  hexlify,
+  keccak256,
+} from "ethers";
+
+// must match the layouts in the program's tx_hash module
+export const TX_HASH_V1 = 1; // legacy, single instructions without length prefixes
+export const TX_HASH_V2 = 2; // canonical, counts and lengths before variable length fields
+
+// bits of an owner's roles, must match the program's roles module
+export const ROLE_VOTER = 1;
+export const ROLE_PROPOSER = 2;
+export const ROLE_CANCELLER = 4;
+
+export type TransactionAccount = {
+  pubkey: PublicKey;
+  isSigner: boolean;
+  isWritable: boolean;
+};
+
+export type MultiSigInstruction = {
+  programId: PublicKey;
+  accounts: TransactionAccount[];
+  data: Buffer;
+};
+
+// paid from the multisig pda after execution, the default mint is SOL and the default
+// receiver whoever relays the transaction
+export type Refund = {
+  amount: anchor.BN;
+  mint: PublicKey;
+  receiver: PublicKey;
+};
+
+// binds a signature to one deployment of the program on one cluster and one layout
+export type TxHashDomain = {
+  programId: PublicKey;
+  chainId: Buffer;
+  version: number;
+};
+
+// the typed data domains also carry the EVM chain id of the owners' wallets
+export type EthTxHashDomain = TxHashDomain & { evmChainId: number };
+
+const u64ToLEBytes = (num: number | anchor.BN): Buffer => {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(BigInt(num.toString()));
+  return buf;
+};
+
+const u32ToLEBytes = (num: number): Buffer => {
+  const buf = Buffer.alloc(4);
+  buf.writeUInt32LE(num);
+  return buf;
+};
+
+const i64ToLEBytes = (num: number | anchor.BN): Buffer => {
+  const buf = Buffer.alloc(8);
+  buf.writeBigInt64LE(BigInt(num.toString()));
+  return buf;
+};
+
+// EIP-191 prefix wallets put in front of a 32 byte message on `personal_sign`
+export const PERSONAL_SIGN_PREFIX = Buffer.from(
+  "\x19Ethereum Signed Message:\n32"
+);
+
+// the secp256k1 precompile message for a `personalSign` execution
+export const personalSignMessage = (txHash: Buffer): Buffer =>
+  Buffer.concat([PERSONAL_SIGN_PREFIX, txHash]);
+
+// the upper 32 bits of a nonce select its lane, see `NonceLane` in the program
+export const laneNonce = (
+  lane: number,
+  sequence: number | anchor.BN = 0
+): anchor.BN => new anchor.BN(lane).shln(32).add(new anchor.BN(sequence));
+
+export const findNonceLanePda = (
+  programId: PublicKey,
+  config: PublicKey,
+  lane: number
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [Buffer.from("nonce-lane"), config.toBuffer(), u32ToLEBytes(lane)],
+    programId
+  )[0];
+
+// configs made with `create_with_key` live at a pda of their creator and create key
+export const findConfigPda = (
+  programId: PublicKey,
+  creator: PublicKey,
+  createKey: PublicKey
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [Buffer.from("multisig-config"), creator.toBuffer(), createKey.toBuffer()],
+    programId
+  )[0];
+
+export const findVaultPda = (
+  programId: PublicKey,
+  config: PublicKey,
+  index: number
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [Buffer.from("vault"), config.toBuffer(), Buffer.from([index])],
+    programId
+  )[0];
+
+// the pda a vault signs with, vault 0 is the config's multisig pda
+export const findVaultSignerPda = (
+  programId: PublicKey,
+  config: PublicKey,
+  index: number
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("multisig-signer"),
+      config.toBuffer(),
+      ...(index === 0 ? [] : [Buffer.from([index])]),
+    ],
+    programId
+  )[0];
+
+// the mint of SOL allowances is the default pubkey
+export const findAllowancePda = (
+  programId: PublicKey,
+  config: PublicKey,
+  delegate: PublicKey,
+  mint: PublicKey = PublicKey.default
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("allowance"),
+      config.toBuffer(),
+      delegate.toBuffer(),
+      mint.toBuffer(),
+    ],
+    programId
+  )[0];
+
+// anchor's tag in front of the events emitted through `emit_cpi!`
+const EVENT_IX_TAG_LE = Buffer.from("e445a52e51cb9a1d", "hex");
+
+// events are self invocations of the program, so they are read from the inner instructions
+// instead of the logs, which can be truncated
+export function parseCpiEvents(
+  program: anchor.Program<any>,
+  tx: anchor.web3.VersionedTransactionResponse
+): anchor.Event[] {
+  const keys = tx.transaction.message.getAccountKeys({
+    accountKeysFromLookups: tx.meta.loadedAddresses,
+  });
+  return tx.meta.innerInstructions
+    .flatMap((inner) => inner.instructions)
+    .filter((ix) => keys.get(ix.programIdIndex).equals(program.programId))
+    .map((ix) => Buffer.from(anchor.utils.bytes.bs58.decode(ix.data)))
+    .filter((data) => data.subarray(0, 8).equals(EVENT_IX_TAG_LE))
+    .map((data) =>
+      program.coder.events.decode(data.subarray(8).toString("base64"))
+    )
+    .filter((event) => event !== null);
+}
+
+// the genesis hash is the default chain id, it differs between localnet, devnet and mainnet
+export async function getChainId(connection: Connection): Promise<Buffer> {
+  const genesisHash = await connection.getGenesisHash();
+  return Buffer.from(anchor.utils.bytes.bs58.decode(genesisHash));
+}
+
+const encodeAccounts = (accounts: TransactionAccount[]): Buffer[] =>
+  accounts.map((account) =>
+    Buffer.concat([
+      Buffer.from(account.pubkey.toBytes()),
+      Buffer.from([account.isSigner ? 1 : 0, account.isWritable ? 1 : 0]),
+    ])
+  );
+
+export function createMultiSigBatchTxHash(
+  domain: TxHashDomain,
+  multisigPda: PublicKey,
+  nonce: anchor.BN,
+  validAfter: anchor.BN,
+  validUntil: anchor.BN,
+  instructions: MultiSigInstruction[],
+  refund?: Refund
+): Buffer {
+  const payload: Buffer[] = [];
+
+  payload.push(Buffer.from([domain.version]));
+  payload.push(Buffer.from(domain.programId.toBytes()));
+  payload.push(domain.chainId);
+
+  payload.push(Buffer.from(multisigPda.toBytes()));
+  payload.push(u64ToLEBytes(nonce));
+  payload.push(i64ToLEBytes(validAfter));
+  payload.push(i64ToLEBytes(validUntil));
+  if (domain.version === TX_HASH_V1) {
+    // without counts a batch could pass for one instruction carrying the rest
+    if (instructions.length !== 1) {
+      throw new Error("batches are only signed with the canonical layout");
+    }
+    for (const instruction of instructions) {
+      payload.push(...encodeAccounts(instruction.accounts));
+      payload.push(Buffer.from(instruction.programId.toBytes()));
+      payload.push(instruction.data);
+    }
+  } else if (domain.version === TX_HASH_V2) {
+    payload.push(u32ToLEBytes(instructions.length));
+    for (const instruction of instructions) {
+      payload.push(Buffer.from(instruction.programId.toBytes()));
+      payload.push(u32ToLEBytes(instruction.accounts.length));
+      payload.push(...encodeAccounts(instruction.accounts));
+      payload.push(u32ToLEBytes(instruction.data.length));
+      payload.push(instruction.data);
+    }
+  } else {
+    throw new Error(`unsupported transaction hash version ${domain.version}`);
+  }
+  // only hashed when there is one, refunds need the canonical layout
+  if (refund) {
+    if (domain.version !== TX_HASH_V2) {
+      throw new Error("refunds are only signed with the canonical layout");
+    }
+    payload.push(u64ToLEBytes(refund.amount));
+    payload.push(Buffer.from(refund.mint.toBytes()));
+    payload.push(Buffer.from(refund.receiver.toBytes()));
+  }
+
+  return Buffer.from(keccak_256.arrayBuffer(Buffer.concat(payload)));
+}
+
+// what the owners sign to burn `nonce`, see `create_cancel_hash` in the program
+export function createCancelHash(
+  domain: TxHashDomain,
+  multisigPda: PublicKey,
+  nonce: anchor.BN
+): Buffer {
+  const payload = Buffer.concat([
+    Buffer.from("cancel"),
+    Buffer.from([domain.version]),
+    Buffer.from(domain.programId.toBytes()),
+    domain.chainId,
+    Buffer.from(multisigPda.toBytes()),
+    u64ToLEBytes(nonce),
+  ]);
+
+  return Buffer.from(keccak_256.arrayBuffer(payload));
+}
+
+// `execute` hashes its instruction as a batch of one
+export function createMultiSigTxHash(
+  domain: TxHashDomain,
+  multisigPda: PublicKey,
+  nonce: anchor.BN,
+  validAfter: anchor.BN,
+  validUntil: anchor.BN,
+  accounts: TransactionAccount[],
+  data: Buffer,
+  program: PublicKey,
+  refund?: Refund
+): Buffer {
+  return createMultiSigBatchTxHash(
+    domain,
+    multisigPda,
+    nonce,
+    validAfter,
+    validUntil,
+    [{ programId: program, accounts, data }],
+    refund
+  );
+}
+
+// must match the type strings in the program's eip712 module
+export const EIP712_TYPES = {
+  SolanaMultisigTx: [
+    { name: "multisigPda", type: "bytes32" },
+    { name: "nonce", type: "uint64" },
+    { name: "validAfter", type: "int64" },
+    { name: "validUntil", type: "int64" },
+    { name: "instructions", type: "Instruction[]" },
+  ],
+  Instruction: [
+    { name: "programId", type: "bytes32" },
+    { name: "accounts", type: "AccountMeta[]" },
+    { name: "data", type: "bytes" },
+  ],
+  AccountMeta: [
+    { name: "pubkey", type: "bytes32" },
+    { name: "isSigner", type: "bool" },
+    { name: "isWritable", type: "bool" },
+  ],
+};
+
+// a transaction with a refund is its own type, so approvals without one keep their digest
+export const EIP712_REFUND_TYPES = {
+  SolanaMultisigRefundTx: [
+    ...EIP712_TYPES.SolanaMultisigTx,
+    { name: "refund", type: "Refund" },
+  ],
+  Instruction: EIP712_TYPES.Instruction,
+  AccountMeta: EIP712_TYPES.AccountMeta,
+  Refund: [
+    { name: "amount", type: "uint64" },
+    { name: "mint", type: "bytes32" },
+    { name: "receiver", type: "bytes32" },
+  ],
+};
+
+export const EIP712_CANCEL_TYPES = {
+  SolanaMultisigCancel: [
+    { name: "multisigPda", type: "bytes32" },
+    { name: "nonce", type: "uint64" },
+  ],
+};
+
+// `verifyingContract` only holds 20 byte addresses, so it is the ethereum style
+// address of the program id and the cluster's chain id goes into `salt`
+export const eip712Domain = (domain: EthTxHashDomain) => ({
+  name: "Stateless Eth Multisig",
+  version: "1",
+  chainId: domain.evmChainId,
+  verifyingContract: ethAddress(domain.programId),
+  salt: hexlify(domain.chainId),
+});
+
+export const eip712Value = (
+  multisigPda: PublicKey,
+  nonce: anchor.BN,
+  validAfter: anchor.BN,
+  validUntil: anchor.BN,
+  instructions: MultiSigInstruction[],
+  refund?: Refund
+) => ({
+  multisigPda: hexlify(multisigPda.toBytes()),
+  nonce: BigInt(nonce.toString()),
+  validAfter: BigInt(validAfter.toString()),
+  validUntil: BigInt(validUntil.toString()),
+  instructions: instructions.map((instruction) => ({
+    programId: hexlify(instruction.programId.toBytes()),
+    accounts: instruction.accounts.map((account) => ({
+      pubkey: hexlify(account.pubkey.toBytes()),
+      isSigner: account.isSigner,
+      isWritable: account.isWritable,
+    })),
+    data: hexlify(instruction.data),
+  })),
+  ...(refund && {
+    refund: {
+      amount: BigInt(refund.amount.toString()),
+      mint: hexlify(refund.mint.toBytes()),
+      receiver: hexlify(refund.receiver.toBytes()),
+    },
+  }),
+});
+
+// the secp256k1 precompile message for an `eip712` execution, the precompile hashes it into the digest
+export const eip712Message = (
+  domain: EthTxHashDomain,
+  value: ReturnType<typeof eip712Value>
+): Buffer =>
+  Buffer.concat([
+    Buffer.from([0x19, 0x01]),
+    getBytes(TypedDataEncoder.hashDomain(eip712Domain(domain))),
+    getBytes(
+      TypedDataEncoder.from(
+        "refund" in value ? EIP712_REFUND_TYPES : EIP712_TYPES
+      ).hash(value)
+    ),
+  ]);
+
+export const eip712CancelValue = (multisigPda: PublicKey, nonce: anchor.BN) => ({
+  multisigPda: hexlify(multisigPda.toBytes()),
+  nonce: BigInt(nonce.toString()),
+});
+
+// the secp256k1 precompile message for an `eip712` cancel
+export const eip712CancelMessage = (
+  domain: EthTxHashDomain,
+  value: ReturnType<typeof eip712CancelValue>
+): Buffer =>
+  Buffer.concat([
+    Buffer.from([0x19, 0x01]),
+    getBytes(TypedDataEncoder.hashDomain(eip712Domain(domain))),
+    getBytes(TypedDataEncoder.from(EIP712_CANCEL_TYPES).hash(value)),
+  ]);
+
+// must match the type strings in the program's safe_tx module
+export const SAFE_TX_TYPES = {
+  SafeTx: [
+    { name: "to", type: "address" },
+    { name: "value", type: "uint256" },
+    { name: "data", type: "bytes" },
+    { name: "operation", type: "uint8" },
+    { name: "safeTxGas", type: "uint256" },
+    { name: "baseGas", type: "uint256" },
+    { name: "gasPrice", type: "uint256" },
+    { name: "gasToken", type: "address" },
+    { name: "refundReceiver", type: "address" },
+    { name: "nonce", type: "uint256" },
+  ],
+};
+
+// ethereum style address of a solana key, the last 20 bytes of its keccak hash
+export const ethAddress = (key: PublicKey): string =>
+  getAddress(dataSlice(keccak256(key.toBytes()), 12));
+
+// SOL and the relayer are the zero address in SafeTx, like ether and `tx.origin` in Safe
+const refundAddress = (key: PublicKey): string =>
+  key.equals(PublicKey.default) ? ZeroAddress : ethAddress(key);
+
+// borsh encoding of a `MultiSigInstruction`, the SafeTx `data`
+export const encodeInstruction = (instruction: MultiSigInstruction): Buffer =>
+  Buffer.concat([
+    Buffer.from(instruction.programId.toBytes()),
+    u32ToLEBytes(instruction.accounts.length),
+    ...encodeAccounts(instruction.accounts),
+    u32ToLEBytes(instruction.data.length),
+    instruction.data,
+  ]);
+
+// the multisig pda is the verifying contract, the EVM chain id comes from the config
+export const safeTxDomain = (
+  domain: EthTxHashDomain,
+  multisigPda: PublicKey
+) => ({
+  chainId: domain.evmChainId,
+  verifyingContract: ethAddress(multisigPda),
+});
+
+// a refund is the base gas at a gas price of 1, see `refund_words` in the program
+export const safeTxValue = (
+  nonce: anchor.BN,
+  instruction: MultiSigInstruction,
+  refund?: Refund
+) => ({
+  to: ethAddress(instruction.programId),
+  value: 0,
+  data: hexlify(encodeInstruction(instruction)),
+  operation: 0,
+  safeTxGas: 0,
+  baseGas: refund ? BigInt(refund.amount.toString()) : 0,
+  gasPrice: refund ? 1 : 0,
+  gasToken: refund ? refundAddress(refund.mint) : ZeroAddress,
+  refundReceiver: refund ? refundAddress(refund.receiver) : ZeroAddress,
+  nonce: BigInt(nonce.toString()),
+});
+
+// the secp256k1 precompile message for a `safeTx` execution
+export const safeTxMessage = (
+  domain: EthTxHashDomain,
+  multisigPda: PublicKey,
+  value: ReturnType<typeof safeTxValue>
+): Buffer =>
+  Buffer.concat([
+    Buffer.from([0x19, 0x01]),
+    getBytes(TypedDataEncoder.hashDomain(safeTxDomain(domain, multisigPda))),
+    getBytes(TypedDataEncoder.from(SAFE_TX_TYPES).hash(value)),
+  ]);
+
+// Safe's rejection, an empty call from the multisig to itself, is what a `safeTx` cancel signs
+export const safeRejectionValue = (
+  multisigPda: PublicKey,
+  nonce: anchor.BN
+): ReturnType<typeof safeTxValue> => ({
+  to: ethAddress(multisigPda),
+  value: 0,
+  data: "0x",
+  operation: 0,
+  safeTxGas: 0,
+  baseGas: 0,
+  gasPrice: 0,
+  gasToken: ZeroAddress,
+  refundReceiver: ZeroAddress,
+  nonce: BigInt(nonce.toString()),
+});
@@ -55,7 +55,8 @@ export class BatchSecp256k1Signer {
   }
 
   static createVerifySignaturesInstruction(
-    params: Secp256k1SignatureVerifyParams[]
+    params: Secp256k1SignatureVerifyParams[],
+    instructionIndex: number = 0
   ): TransactionInstruction {
     /*
     the Secp256k1SigVerify instruction data is made up of [count][offset metadata][actual data] 
@@ -66,7 +67,8 @@ export class BatchSecp256k1Signer {
     offset metadata[ 
         signature1 offset
         signature1 ix index 
-        (the program can read values from other instructions in the same tx)
+        (the program can read values from other instructions in the same tx,
+        the index is absolute so it must be the position of this instruction in the tx)
         pubkey1 offset (it's the ethereum address)
         pubkey1 ix index
         message1 offset
@@ -116,14 +118,14 @@ export class BatchSecp256k1Signer {
       const headerStart = DATA_START + i * SIGNATURE_OFFSETS_LENGTH;
 
       instructionData.writeUInt16LE(currentSignatureOffset, headerStart);
-      instructionData.writeUInt8(0, headerStart + 2);
+      instructionData.writeUInt8(instructionIndex, headerStart + 2);
 
       instructionData.writeUInt16LE(currentAddressOffset, headerStart + 3);
-      instructionData.writeUInt8(0, headerStart + 5);
+      instructionData.writeUInt8(instructionIndex, headerStart + 5);
 
       instructionData.writeUInt16LE(currentMessageOffset, headerStart + 6);
       instructionData.writeUInt16LE(param.message.length, headerStart + 8);
-      instructionData.writeUInt8(0, headerStart + 10);
+      instructionData.writeUInt8(instructionIndex, headerStart + 10);
 
       let ethAddress: Buffer;
       if (typeof param.ethAddress === "string") {
@@ -154,7 +156,8 @@ export class BatchSecp256k1Signer {
   }
 
   static signAndCreateVerifySignaturesInstruction(
-    params: Secp256k1SignAndVerifyParams[]
+    params: Secp256k1SignAndVerifyParams[],
+    instructionIndex: number = 0
   ): TransactionInstruction {
     const paramsWithSignature: Secp256k1SignatureVerifyParams[] = [];
 
@@ -174,7 +177,8 @@ export class BatchSecp256k1Signer {
     }
 
     return BatchSecp256k1Signer.createVerifySignaturesInstruction(
-      paramsWithSignature
+      paramsWithSignature,
+      instructionIndex
     );
   }
 
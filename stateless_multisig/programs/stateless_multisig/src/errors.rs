@@ -1,4 +1,6 @@
 use anchor_lang::prelude::*;
+use multisig_common::CommonError;
+use precompile_parser::ParseError;
 
 #[error_code]
 pub enum MultiSigErrors {
@@ -8,10 +10,10 @@ pub enum MultiSigErrors {
     NotEnoughSigners,
     #[msg("owners length must be non zero")]
     InvalidOwnersLen,
-    #[msg("threshold must be greater than 0 and less than or equal to owner count")]
+    #[msg("threshold must be greater than 0 and less than or equal to the total owner weight")]
     InvalidThreshold,
-    #[msg("invalid Ed25519 verifier instruction")]
-    InvalidEd25519Instruction,
+    #[msg("invalid Ed25519 or Secp256k1 verifier instruction")]
+    InvalidPrecompileInstruction,
     #[msg("invalid message signer")]
     InvalidMessageSigner,
     #[msg("invalid message")]
@@ -22,4 +24,82 @@ pub enum MultiSigErrors {
     ThresholdNotMet,
     #[msg("invalid signer")]
     InvalidSigner,
+    #[msg("owner not found")]
+    OwnerNotFound,
+    #[msg("batch must contain at least one instruction")]
+    EmptyBatch,
+    #[msg("signature count does not match the number of signers")]
+    SignatureCountMismatch,
+    #[msg("precompile offsets must reference the precompile instruction")]
+    InvalidInstructionIndex,
+    #[msg("precompile instruction data is shorter than its header")]
+    TruncatedPrecompileHeader,
+    #[msg("precompile offset points outside the instruction data")]
+    PrecompileOffsetOutOfRange,
+    #[msg("every owner needs a non zero weight")]
+    InvalidWeights,
+    #[msg("transaction is not valid yet")]
+    TransactionTooEarly,
+    #[msg("transaction has expired")]
+    TransactionExpired,
+    #[msg("unsupported transaction hash version")]
+    InvalidHashVersion,
+    #[msg("nonce lane account does not match the nonce")]
+    InvalidNonceLane,
+    #[msg("guard program account does not match the config")]
+    InvalidGuard,
+    #[msg("module is already enabled")]
+    ModuleAlreadyEnabled,
+    #[msg("module is not enabled")]
+    ModuleNotEnabled,
+    #[msg("allowance period can not be negative")]
+    InvalidAllowancePeriod,
+    #[msg("amount exceeds the remaining allowance")]
+    AllowanceExceeded,
+    #[msg("token accounts do not match the allowance")]
+    InvalidAllowanceAccounts,
+    #[msg("refund or refund accounts do not match the signed refund")]
+    InvalidRefund,
+    #[msg("vault accounts do not match the vault index")]
+    InvalidVault,
+    #[msg("config was closed and can not be created again")]
+    ConfigClosed,
+    #[msg("signers or executor are missing a required role")]
+    MissingRole,
+    #[msg("roles must leave a proposer and enough voting weight for the threshold")]
+    InvalidRoles,
+    #[msg("policy prefix is too long or the policy targets the multisig itself")]
+    InvalidPolicy,
+    #[msg("a policy for this program and prefix already exists")]
+    PolicyAlreadySet,
+    #[msg("policy not found")]
+    PolicyNotFound,
+    #[msg("the config is already executing a transaction")]
+    Reentrancy,
+    #[msg("only the multisig program can take the config as a writable account")]
+    WritableConfig,
+    #[msg("the config still has vaults, remove them before closing it")]
+    VaultsRemaining,
+}
+
+impl From<ParseError> for MultiSigErrors {
+    fn from(err: ParseError) -> Self {
+        match err {
+            ParseError::TruncatedHeader => MultiSigErrors::TruncatedPrecompileHeader,
+            ParseError::OffsetOutOfRange => MultiSigErrors::PrecompileOffsetOutOfRange,
+            ParseError::CountMismatch => MultiSigErrors::SignatureCountMismatch,
+        }
+    }
+}
+
+impl From<CommonError> for MultiSigErrors {
+    fn from(err: CommonError) -> Self {
+        match err {
+            CommonError::InvalidHashVersion => MultiSigErrors::InvalidHashVersion,
+            CommonError::AllowanceExceeded => MultiSigErrors::AllowanceExceeded,
+            CommonError::InvalidAllowanceAccounts => MultiSigErrors::InvalidAllowanceAccounts,
+            CommonError::InvalidRefund => MultiSigErrors::InvalidRefund,
+            CommonError::InvalidThreshold => MultiSigErrors::InvalidThreshold,
+        }
+    }
 }
@@ -1,12 +1,30 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program;
+use anchor_lang::solana_program::ed25519_program::ID as ED25519_ID;
 use anchor_lang::solana_program::instruction::Instruction;
-use anchor_lang::solana_program::keccak;
-use anchor_lang::solana_program::sysvar::instructions::{get_instruction_relative, ID as IX_ID};
+use anchor_lang::solana_program::secp256k1_program::ID as SECP256K1_ID;
+use anchor_lang::solana_program::sysvar::instructions::{load_instruction_at_checked, ID as IX_ID};
 
 pub mod errors;
+pub mod events;
+pub mod roles;
 pub mod verifier;
 
+pub use multisig_common::{
+    allowance, guard, policy, refund, tx_hash, MultiSigInstruction, TransactionAccount,
+};
+
+use events::{
+    AdminThresholdChanged, AllowanceRemoved, AllowanceSet, AllowanceSpent, GuardChanged,
+    HashVersionChanged, ModuleDisabled, ModuleEnabled, ModuleTransactionExecuted, MultisigClosed,
+    MultisigCreated, NonceCancelled, OwnerAdded, OwnerRemoved, OwnerSwapped, PolicyAdded,
+    PolicyRemoved, RolesChanged, ThresholdChanged, TransactionExecuted, VaultChanged, VaultRemoved,
+    WeightChanged,
+};
+use policy::Policy;
+use refund::Refund;
+use tx_hash::{create_cancel_hash, create_multi_sig_tx_hash, TxHashDomain};
+
 declare_id!("8EKj21isKqgxYfMQybmGWHRCn62F5thMxeaHy3A93G6L");
 
 #[program]
@@ -15,111 +33,1058 @@ pub mod stateless_multisig {
 
     pub fn create(
         ctx: Context<CreateMultiSigCtx>,
-        signers: Vec<Pubkey>,
+        signers: Vec<Owner>,
+        weights: Vec<u8>,
         threshold: u8,
+        chain_id: [u8; 32],
+        hash_version: u8,
     ) -> Result<()> {
-        unique_signers(&signers)?;
-        require!(
-            !signers.is_empty(),
-            errors::MultiSigErrors::InvalidOwnersLen
-        );
-        require!(
-            threshold > 0 && threshold <= signers.len() as u8,
-            errors::MultiSigErrors::InvalidThreshold
-        );
+        emit_cpi!(init_config(
+            &mut ctx.accounts.config,
+            ctx.program_id,
+            signers,
+            weights,
+            threshold,
+            chain_id,
+            hash_version,
+        )?);
+
+        Ok(())
+    }
 
-        // Find PDA that will act as the actual multisig signer
-        let (multisig_pda, bump) = Pubkey::find_program_address(
-            &[b"multisig-signer", ctx.accounts.config.key().as_ref()],
+    /// Creates the config at `["multisig-config", creator, create_key]`, so its address is
+    /// known before it exists. The creator signs so nobody else can take the address.
+    pub fn create_with_key(
+        ctx: Context<CreateMultiSigWithKeyCtx>,
+        _create_key: Pubkey,
+        signers: Vec<Owner>,
+        weights: Vec<u8>,
+        threshold: u8,
+        chain_id: [u8; 32],
+        hash_version: u8,
+    ) -> Result<()> {
+        emit_cpi!(init_config(
+            &mut ctx.accounts.config,
             ctx.program_id,
+            signers,
+            weights,
+            threshold,
+            chain_id,
+            hash_version,
+        )?);
+
+        Ok(())
+    }
+
+    /// Closes the config and sends its rent to `receiver`. A `ClosedConfig` marker stays at
+    /// `["closed-config", config]` so the address can not be created again with a reset
+    /// nonce, which would make the approvals of the old config valid again.
+    pub fn close(ctx: Context<CloseMultiSigCtx>) -> Result<()> {
+        // the funds of a vault could not be reached once its config is gone
+        require_eq!(
+            ctx.accounts.config.vaults,
+            0,
+            errors::MultiSigErrors::VaultsRemaining
         );
 
-        // Initialize the configuration account
-        ctx.accounts.config.nonce = 0;
-        ctx.accounts.config.owners = signers;
-        ctx.accounts.config.threshold = threshold;
-        ctx.accounts.config.multisig_pda = multisig_pda;
-        ctx.accounts.config.pda_bump = bump;
+        emit_cpi!(MultisigClosed {
+            config: ctx.accounts.config.key(),
+            receiver: ctx.accounts.receiver.key(),
+        });
 
         Ok(())
     }
 
     pub fn execute(ctx: Context<ExecuteMultiSigTxCtx>, params: ExecuteMultiSigTx) -> Result<()> {
-        // check signers are unique and above threshold
-        unique_signers(&params.signers)?;
-        require_gte!(
-            params.signers.len(),
-            ctx.accounts.config.threshold as usize,
-            errors::MultiSigErrors::ThresholdNotMet
-        );
-        // verify nonce to prevent replay
+        let params = ExecuteMultiSigBatchTx {
+            instructions: vec![MultiSigInstruction {
+                program_id: params.program_id,
+                accounts: params.accounts,
+                data: params.data,
+            }],
+            vault_index: params.vault_index,
+            signers: params.signers,
+            nonce: params.nonce,
+            valid_after: params.valid_after,
+            valid_until: params.valid_until,
+            precompile_ix_indices: params.precompile_ix_indices,
+            refund: params.refund,
+        };
+
+        execute_instructions(ctx, params)
+    }
+
+    pub fn execute_batch(
+        ctx: Context<ExecuteMultiSigTxCtx>,
+        params: ExecuteMultiSigBatchTx,
+    ) -> Result<()> {
+        require!(
+            !params.instructions.is_empty(),
+            errors::MultiSigErrors::EmptyBatch
+        );
+
+        execute_instructions(ctx, params)
+    }
+
+    /// Opens an independent nonce lane, see `NonceLane`. Anyone can pay for a lane since
+    /// every nonce in it still needs the owners' approval.
+    pub fn open_lane(ctx: Context<OpenLaneCtx>, lane: u32) -> Result<()> {
+        require_neq!(lane, 0, errors::MultiSigErrors::InvalidNonceLane);
+
+        ctx.accounts.nonce_lane.config = ctx.accounts.config.key();
+        ctx.accounts.nonce_lane.lane = lane;
+        ctx.accounts.nonce_lane.nonce = (lane as u64) << 32;
+
+        Ok(())
+    }
+
+    /// Burns the pending nonce without executing anything, invalidating every proposal signed
+    /// for it. The owners sign a cancel message instead of a transaction hash.
+    pub fn cancel(ctx: Context<ExecuteMultiSigTxCtx>, params: CancelMultiSigTx) -> Result<()> {
+        require!(
+            !ctx.accounts.config.executing,
+            errors::MultiSigErrors::Reentrancy
+        );
+        // the owners who can execute from the vault can also cancel for it
+        match ctx.accounts.vault(params.vault_index)? {
+            Some(vault) if !vault.owners.is_empty() => check_vault_threshold(
+                &ctx.accounts.config,
+                vault,
+                &params.signers,
+                vault.threshold,
+            )?,
+            _ => check_threshold(&ctx.accounts.config, &params.signers)?,
+        }
+        require_role(&ctx.accounts.config, &params.signers, roles::CANCELLER)?;
+        // only the pending nonce of the vault, or of a lane of the multisig pda, can be cancelled
         require_eq!(
             params.nonce,
-            ctx.accounts.config.nonce,
+            *ctx.accounts
+                .pending_nonce(params.vault_index, params.nonce)?,
             errors::MultiSigErrors::ErrNonceTooOld
         );
 
-        // verify all signers are owners
-        for signer in params.signers.iter() {
-            require!(
-                ctx.accounts.config.owners.contains(signer),
-                errors::MultiSigErrors::InvalidSigner
+        let domain = TxHashDomain {
+            program_id: *ctx.program_id,
+            chain_id: ctx.accounts.config.chain_id,
+            version: ctx.accounts.config.hash_version,
+        };
+        // like approvals, a cancel is bound to the vault through its pda
+        let (vault_pda, _) = ctx.accounts.signing_pda(params.vault_index)?;
+        let expected_hash = create_cancel_hash(&domain, vault_pda.key(), params.nonce)
+            .map_err(errors::MultiSigErrors::from)?;
+        msg!("expected hash {:02x?}", expected_hash);
+        verify_signatures(
+            &ctx.accounts.ix_sysvar,
+            &params.signers,
+            &params.precompile_ix_indices,
+            expected_hash,
+        )?;
+
+        *ctx.accounts
+            .pending_nonce(params.vault_index, params.nonce)? += 1;
+        emit_cpi!(NonceCancelled {
+            config: ctx.accounts.config.key(),
+            vault_index: params.vault_index,
+            nonce: params.nonce,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_owner(
+        ctx: Context<AddOwnerCtx>,
+        owner: Owner,
+        weight: u8,
+        threshold: u8,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.owners.push(owner);
+        config.weights.push(weight);
+        config.roles.push(roles::DEFAULT);
+        validate_owners(&config.owners, &config.weights, &config.roles, threshold)?;
+        config.threshold = threshold;
+        validate_policies(config)?;
+
+        emit_cpi!(OwnerAdded {
+            config: ctx.accounts.config.key(),
+            owner,
+            weight,
+            threshold,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_owner(ctx: Context<RemoveOwnerCtx>, owner: Owner, threshold: u8) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let index = config.owner_index(&owner)?;
+        config.owners.remove(index);
+        config.weights.remove(index);
+        config.roles.remove(index);
+        validate_owners(&config.owners, &config.weights, &config.roles, threshold)?;
+        config.threshold = threshold;
+        validate_policies(config)?;
+        validate_vaults(config, ctx.remaining_accounts)?;
+
+        emit_cpi!(OwnerRemoved {
+            config: ctx.accounts.config.key(),
+            owner,
+            threshold,
+        });
+
+        Ok(())
+    }
+
+    pub fn swap_owner(
+        ctx: Context<UpdateConfigCtx>,
+        old_owner: Owner,
+        new_owner: Owner,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        // the new owner takes over the weight of the old one
+        let index = config.owner_index(&old_owner)?;
+        config.owners[index] = new_owner;
+        validate_owners(
+            &config.owners,
+            &config.weights,
+            &config.roles,
+            config.threshold,
+        )?;
+        // the vaults list owners by key, so the old one must not be in any of them
+        validate_vaults(config, ctx.remaining_accounts)?;
+
+        emit_cpi!(OwnerSwapped {
+            config: ctx.accounts.config.key(),
+            old_owner,
+            new_owner,
+        });
+
+        Ok(())
+    }
+
+    pub fn change_weight(ctx: Context<UpdateConfigCtx>, owner: Owner, weight: u8) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let index = config.owner_index(&owner)?;
+        config.weights[index] = weight;
+        validate_owners(
+            &config.owners,
+            &config.weights,
+            &config.roles,
+            config.threshold,
+        )?;
+        validate_policies(config)?;
+        validate_vaults(config, ctx.remaining_accounts)?;
+
+        emit_cpi!(WeightChanged {
+            config: ctx.accounts.config.key(),
+            owner,
+            weight,
+        });
+
+        Ok(())
+    }
+
+    /// Replaces the roles of `owner`, a combination of the bits in `roles`.
+    pub fn set_roles(ctx: Context<UpdateConfigCtx>, owner: Owner, roles: u8) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let index = config.owner_index(&owner)?;
+        // executors sign the solana transaction
+        require!(
+            !roles::has(roles, roles::EXECUTOR) || matches!(owner, Owner::Ed25519(_)),
+            errors::MultiSigErrors::InvalidRoles
+        );
+        config.roles[index] = roles;
+        validate_owners(
+            &config.owners,
+            &config.weights,
+            &config.roles,
+            config.threshold,
+        )?;
+        validate_policies(config)?;
+        validate_vaults(config, ctx.remaining_accounts)?;
+
+        emit_cpi!(RolesChanged {
+            config: ctx.accounts.config.key(),
+            owner,
+            roles,
+        });
+
+        Ok(())
+    }
+
+    pub fn change_hash_version(ctx: Context<UpdateConfigCtx>, hash_version: u8) -> Result<()> {
+        tx_hash::validate_version(hash_version).map_err(errors::MultiSigErrors::from)?;
+        ctx.accounts.config.hash_version = hash_version;
+
+        emit_cpi!(HashVersionChanged {
+            config: ctx.accounts.config.key(),
+            hash_version,
+        });
+
+        Ok(())
+    }
+
+    /// Sets or removes the guard program, see `guard`. A new guard applies from the next
+    /// transaction on.
+    pub fn set_guard(ctx: Context<UpdateConfigCtx>, guard: Option<Pubkey>) -> Result<()> {
+        // the multisig has no guard hooks, it would veto every transaction
+        require!(
+            guard != Some(*ctx.program_id),
+            errors::MultiSigErrors::InvalidGuard
+        );
+        ctx.accounts.config.guard = guard;
+
+        emit_cpi!(GuardChanged {
+            config: ctx.accounts.config.key(),
+            guard,
+        });
+
+        Ok(())
+    }
+
+    /// Lets `module` execute through the multisig pda without approvals, see
+    /// `execute_from_module`. A module can do anything the owners can. Like in Safe any key
+    /// can be a module, wallets included, it is up to the owners to enable a program pda.
+    pub fn enable_module(ctx: Context<EnableModuleCtx>, module: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(
+            !config.modules.contains(&module),
+            errors::MultiSigErrors::ModuleAlreadyEnabled
+        );
+        config.modules.push(module);
+
+        emit_cpi!(ModuleEnabled {
+            config: ctx.accounts.config.key(),
+            module,
+        });
+
+        Ok(())
+    }
+
+    pub fn disable_module(ctx: Context<DisableModuleCtx>, module: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let index = config.module_index(&module)?;
+        config.modules.remove(index);
+
+        emit_cpi!(ModuleDisabled {
+            config: ctx.accounts.config.key(),
+            module,
+        });
+
+        Ok(())
+    }
+
+    /// Runs `instructions` with the multisig pda seeds for an enabled module, which signs with
+    /// its program derived address. Module calls use no nonce and are not checked by the guard.
+    pub fn execute_from_module(
+        ctx: Context<ExecuteFromModuleCtx>,
+        instructions: Vec<MultiSigInstruction>,
+    ) -> Result<()> {
+        require!(!instructions.is_empty(), errors::MultiSigErrors::EmptyBatch);
+        require!(
+            !ctx.accounts.config.executing,
+            errors::MultiSigErrors::Reentrancy
+        );
+        ctx.accounts
+            .config
+            .module_index(&ctx.accounts.module.key())?;
+        check_targets(&instructions, &ctx.accounts.config.key(), ctx.program_id)?;
+
+        msg!("executing from module {}", ctx.accounts.module.key());
+        enter_execution(&mut ctx.accounts.config, ctx.program_id)?;
+        let config_key = ctx.accounts.config.key();
+        let (index, bump) = ([0], [ctx.accounts.config.pda_bump]);
+        let instruction_count = instructions.len() as u32;
+        invoke_instructions(
+            instructions,
+            &ctx.accounts.multisig_pda,
+            &[&vault_seeds(&config_key, &index, &bump)],
+            ctx.remaining_accounts,
+        )?;
+        leave_execution(&mut ctx.accounts.config)?;
+
+        emit_cpi!(ModuleTransactionExecuted {
+            config: config_key,
+            module: ctx.accounts.module.key(),
+            instruction_count,
+        });
+        Ok(())
+    }
+
+    /// Grants `delegate` an allowance of `amount` of `mint` per `period` seconds, see
+    /// `Allowance`. Updating an allowance keeps what was spent in the current period.
+    pub fn set_allowance(
+        ctx: Context<SetAllowanceCtx>,
+        delegate: Pubkey,
+        mint: Pubkey,
+        amount: u64,
+        period: i64,
+    ) -> Result<()> {
+        require_gte!(period, 0, errors::MultiSigErrors::InvalidAllowancePeriod);
+
+        let allowance = &mut ctx.accounts.allowance;
+        if allowance.config == Pubkey::default() {
+            allowance.config = ctx.accounts.config.key();
+            allowance.delegate = delegate;
+            allowance.mint = mint;
+            allowance.spent = 0;
+            allowance.period_start = Clock::get()?.unix_timestamp;
+            allowance.bump = ctx.bumps.allowance;
+        }
+        allowance.amount = amount;
+        allowance.period = period;
+
+        emit_cpi!(AllowanceSet {
+            config: ctx.accounts.config.key(),
+            delegate,
+            mint,
+            amount,
+            period,
+        });
+
+        Ok(())
+    }
+
+    // the allowance account is closed to the multisig pda by the context
+    pub fn remove_allowance(ctx: Context<RemoveAllowanceCtx>) -> Result<()> {
+        emit_cpi!(AllowanceRemoved {
+            config: ctx.accounts.config.key(),
+            delegate: ctx.accounts.allowance.delegate,
+            mint: ctx.accounts.allowance.mint,
+        });
+
+        Ok(())
+    }
+
+    /// Lets a delegate spend from the multisig pda within its allowance, without approvals.
+    pub fn spend_allowance(ctx: Context<SpendAllowanceCtx>, amount: u64) -> Result<()> {
+        ctx.accounts
+            .allowance
+            .spend(amount, Clock::get()?.unix_timestamp)?;
+
+        let config_key = ctx.accounts.config.key();
+        let (index, bump) = ([0], [ctx.accounts.config.pda_bump]);
+        let seeds = vault_seeds(&config_key, &index, &bump);
+
+        if ctx.accounts.allowance.mint == Pubkey::default() {
+            allowance::transfer_lamports(
+                &ctx.accounts.multisig_pda,
+                &ctx.accounts.recipient,
+                amount,
+                &[&seeds],
+            )?;
+        } else {
+            let (Some(vault), Some(mint), Some(token_program)) = (
+                &ctx.accounts.vault,
+                &ctx.accounts.mint,
+                &ctx.accounts.token_program,
+            ) else {
+                return err!(errors::MultiSigErrors::InvalidAllowanceAccounts);
+            };
+            require_keys_eq!(
+                mint.key(),
+                ctx.accounts.allowance.mint,
+                errors::MultiSigErrors::InvalidAllowanceAccounts
             );
+            allowance::transfer_tokens::<errors::MultiSigErrors>(
+                vault,
+                mint,
+                &ctx.accounts.recipient,
+                &ctx.accounts.multisig_pda,
+                token_program,
+                amount,
+                &[&seeds],
+            )?;
         }
 
-        msg!("getting instruction");
+        emit_cpi!(AllowanceSpent {
+            config: config_key,
+            delegate: ctx.accounts.delegate.key(),
+            mint: ctx.accounts.allowance.mint,
+            recipient: ctx.accounts.recipient.key(),
+            amount,
+        });
 
-        // the instruction before execute should always be the call to the Ed25519 precompile
-        let ix: Instruction = get_instruction_relative(-1, &ctx.accounts.ix_sysvar)?;
+        Ok(())
+    }
+
+    /// Adds vault `index`, with its own owners and threshold or with the config's if `owners`
+    /// is empty, see `Vault`.
+    pub fn add_vault(
+        ctx: Context<AddVaultCtx>,
+        index: u8,
+        owners: Vec<Owner>,
+        threshold: u8,
+    ) -> Result<()> {
+        // vault 0 is the config's multisig pda
+        require_neq!(index, 0, errors::MultiSigErrors::InvalidVault);
+        validate_vault_owners(&ctx.accounts.config, &owners, threshold)?;
 
-        let expected_hash = create_multi_sig_tx_hash(
-            ctx.accounts.multisig_pda.key(),
-            ctx.accounts.config.nonce,
-            params.accounts.clone(),
-            &params.data,
-            params.program_id,
+        let config_key = ctx.accounts.config.key();
+        let (_, vault_bump) = Pubkey::find_program_address(
+            &[b"multisig-signer", config_key.as_ref(), &[index]],
+            ctx.program_id,
         );
-        msg!("expected hash {:02x?}", expected_hash);
-        verifier::verify(&ix, params.signers, expected_hash)?;
+        let vault = &mut ctx.accounts.vault;
+        vault.config = config_key;
+        vault.index = index;
+        vault.owners = owners;
+        vault.threshold = threshold;
+        vault.nonce = (ctx.accounts.config.vaults_added as u64) << 32;
+        vault.vault_bump = vault_bump;
+        let config = &mut ctx.accounts.config;
+        config.vaults += 1;
+        config.vaults_added += 1;
 
-        msg!("verified sigs");
-        // increment nonce
-        ctx.accounts.config.nonce += 1;
+        emit_cpi!(VaultChanged {
+            config: config_key,
+            index,
+            owners: ctx.accounts.vault.owners.clone(),
+            threshold,
+        });
 
-        let accounts: Vec<AccountMeta> = params
+        Ok(())
+    }
+
+    pub fn change_vault_owners(
+        ctx: Context<ChangeVaultOwnersCtx>,
+        owners: Vec<Owner>,
+        threshold: u8,
+    ) -> Result<()> {
+        validate_vault_owners(&ctx.accounts.config, &owners, threshold)?;
+        let vault = &mut ctx.accounts.vault;
+        vault.owners = owners;
+        vault.threshold = threshold;
+
+        emit_cpi!(VaultChanged {
+            config: ctx.accounts.config.key(),
+            index: ctx.accounts.vault.index,
+            owners: ctx.accounts.vault.owners.clone(),
+            threshold,
+        });
+
+        Ok(())
+    }
+
+    /// Closes the vault account to the multisig pda. Funds stay in the vault's pda and can be
+    /// reached again by adding the vault back.
+    pub fn remove_vault(ctx: Context<RemoveVaultCtx>) -> Result<()> {
+        ctx.accounts.config.vaults -= 1;
+        emit_cpi!(VaultRemoved {
+            config: ctx.accounts.config.key(),
+            index: ctx.accounts.vault.index,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the threshold of the instructions to `program_id` whose data starts with `prefix`,
+    /// see `policy`.
+    pub fn add_policy(
+        ctx: Context<AddPolicyCtx>,
+        program_id: Pubkey,
+        prefix: Vec<u8>,
+        threshold: u8,
+    ) -> Result<()> {
+        // instructions to the multisig itself use the admin threshold
+        require!(
+            program_id != *ctx.program_id && prefix.len() <= Policy::MAX_PREFIX,
+            errors::MultiSigErrors::InvalidPolicy
+        );
+        let config = &mut ctx.accounts.config;
+        require!(
+            config.policy_index(&program_id, &prefix).is_err(),
+            errors::MultiSigErrors::PolicyAlreadySet
+        );
+        config.policies.push(Policy {
+            program_id,
+            prefix: prefix.clone(),
+            threshold,
+        });
+        validate_policies(config)?;
+
+        emit_cpi!(PolicyAdded {
+            config: ctx.accounts.config.key(),
+            program_id,
+            prefix,
+            threshold,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_policy(
+        ctx: Context<RemovePolicyCtx>,
+        program_id: Pubkey,
+        prefix: Vec<u8>,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let index = config.policy_index(&program_id, &prefix)?;
+        config.policies.remove(index);
+
+        emit_cpi!(PolicyRemoved {
+            config: ctx.accounts.config.key(),
+            program_id,
+            prefix,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the threshold of instructions to the multisig program itself, 0 to use the
+    /// config's threshold for them.
+    pub fn change_admin_threshold(ctx: Context<UpdateConfigCtx>, threshold: u8) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin_threshold = threshold;
+        validate_policies(config)?;
+
+        emit_cpi!(AdminThresholdChanged {
+            config: ctx.accounts.config.key(),
+            threshold,
+        });
+
+        Ok(())
+    }
+
+    pub fn change_threshold(ctx: Context<UpdateConfigCtx>, threshold: u8) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        validate_owners(&config.owners, &config.weights, &config.roles, threshold)?;
+        config.threshold = threshold;
+        validate_policies(config)?;
+
+        emit_cpi!(ThresholdChanged {
+            config: ctx.accounts.config.key(),
+            threshold,
+        });
+
+        Ok(())
+    }
+}
+
+// validates and stores the initial config, shared by both ways of creating it
+fn init_config(
+    config: &mut Account<MultiSigConfig>,
+    program_id: &Pubkey,
+    signers: Vec<Owner>,
+    weights: Vec<u8>,
+    threshold: u8,
+    chain_id: [u8; 32],
+    hash_version: u8,
+) -> Result<MultisigCreated> {
+    let roles = vec![roles::DEFAULT; signers.len()];
+    validate_owners(&signers, &weights, &roles, threshold)?;
+    tx_hash::validate_version(hash_version).map_err(errors::MultiSigErrors::from)?;
+
+    // Find PDA that will act as the actual multisig signer
+    let (multisig_pda, bump) =
+        Pubkey::find_program_address(&[b"multisig-signer", config.key().as_ref()], program_id);
+
+    config.nonce = 0;
+    config.owners = signers;
+    config.weights = weights;
+    config.roles = roles;
+    config.threshold = threshold;
+    config.admin_threshold = 0;
+    config.policies = Vec::new();
+    config.chain_id = chain_id;
+    config.hash_version = hash_version;
+    config.guard = None;
+    config.modules = Vec::new();
+    config.vaults = 0;
+    config.vaults_added = 0;
+    config.multisig_pda = multisig_pda;
+    config.pda_bump = bump;
+    config.executing = false;
+
+    Ok(MultisigCreated {
+        config: config.key(),
+        multisig_pda,
+        owners: config.owners.clone(),
+        weights: config.weights.clone(),
+        threshold,
+        chain_id,
+        hash_version,
+    })
+}
+
+fn execute_instructions(
+    ctx: Context<ExecuteMultiSigTxCtx>,
+    params: ExecuteMultiSigBatchTx,
+) -> Result<()> {
+    require!(
+        !ctx.accounts.config.executing,
+        errors::MultiSigErrors::Reentrancy
+    );
+    check_targets(
+        &params.instructions,
+        &ctx.accounts.config.key(),
+        ctx.program_id,
+    )?;
+    // a vault with its own owners is only controlled by them, policies can still ask for more
+    match ctx.accounts.vault(params.vault_index)? {
+        Some(vault) if !vault.owners.is_empty() => check_vault_threshold(
+            &ctx.accounts.config,
+            vault,
+            &params.signers,
+            policy::required_vault_threshold(
+                &ctx.accounts.config.thresholds(),
+                vault.threshold,
+                ctx.program_id,
+                &params.instructions,
+            ),
+        )?,
+        // the config's owners need the threshold of the policies the instructions fall under
+        _ => check_weight(
+            &ctx.accounts.config,
+            &params.signers,
+            policy::required_threshold(
+                &ctx.accounts.config.thresholds(),
+                ctx.program_id,
+                &params.instructions,
+            ),
+        )?,
+    }
+    require_role(&ctx.accounts.config, &params.signers, roles::PROPOSER)?;
+    ctx.accounts.check_executor()?;
+    // verify nonce to prevent replay, each lane and each vault executes in order
+    require_eq!(
+        params.nonce,
+        *ctx.accounts
+            .pending_nonce(params.vault_index, params.nonce)?,
+        errors::MultiSigErrors::ErrNonceTooOld
+    );
+    // approvals only hold within the signed validity window
+    let now = Clock::get()?.unix_timestamp;
+    require_gte!(
+        now,
+        params.valid_after,
+        errors::MultiSigErrors::TransactionTooEarly
+    );
+    require_gte!(
+        params.valid_until,
+        now,
+        errors::MultiSigErrors::TransactionExpired
+    );
+
+    let domain = TxHashDomain {
+        program_id: *ctx.program_id,
+        chain_id: ctx.accounts.config.chain_id,
+        version: ctx.accounts.config.hash_version,
+    };
+    // approvals are bound to the vault through its pda
+    let (vault_pda, vault_bump) = ctx.accounts.signing_pda(params.vault_index)?;
+    let expected_hash = create_multi_sig_tx_hash(
+        &domain,
+        vault_pda.key(),
+        params.nonce,
+        params.valid_after,
+        params.valid_until,
+        &params.instructions,
+        params.refund.as_ref(),
+    )
+    .map_err(errors::MultiSigErrors::from)?;
+    msg!("expected hash {:02x?}", expected_hash);
+
+    verify_signatures(
+        &ctx.accounts.ix_sysvar,
+        &params.signers,
+        &params.precompile_ix_indices,
+        expected_hash,
+    )?;
+
+    msg!("verified sigs");
+    // increment nonce
+    *ctx.accounts
+        .pending_nonce(params.vault_index, params.nonce)? += 1;
+
+    enter_execution(&mut ctx.accounts.config, ctx.program_id)?;
+    if let Some(nonce_lane) = &ctx.accounts.nonce_lane {
+        nonce_lane.exit(ctx.program_id)?;
+    }
+    if let Some(vault) = &ctx.accounts.vault {
+        vault.exit(ctx.program_id)?;
+    }
+
+    // like Safe, the guard set before execution checks the transaction on both sides of it,
+    // a guard set by the transaction itself only applies from the next one
+    let guard = match ctx.accounts.guard_program()? {
+        Some(program) => {
+            let args = guard::hook_args(
+                ctx.accounts.config.key(),
+                params.nonce,
+                &params.instructions,
+                params.refund.as_ref(),
+            )?;
+            guard::check(
+                &program,
+                guard::CHECK_TRANSACTION,
+                &args,
+                &ctx.accounts.config.to_account_info(),
+                &vault_pda,
+            )?;
+            Some((program, args))
+        }
+        None => None,
+    };
+
+    let programs = params
+        .instructions
+        .iter()
+        .map(|instruction| instruction.program_id)
+        .collect();
+    let config_key = ctx.accounts.config.key();
+    let (index, bump) = ([params.vault_index], [vault_bump]);
+    let seeds = vault_seeds(&config_key, &index, &bump);
+    invoke_instructions(
+        params.instructions,
+        &vault_pda,
+        &[&seeds],
+        ctx.remaining_accounts,
+    )?;
+
+    leave_execution(&mut ctx.accounts.config)?;
+
+    // like Safe's gas refund, the relayer is only paid once every instruction succeeded
+    if let Some(refund) = &params.refund {
+        ctx.accounts.pay_refund(refund, &vault_pda, &[&seeds])?;
+    }
+
+    if let Some((program, args)) = &guard {
+        guard::check(
+            program,
+            guard::CHECK_AFTER_EXECUTION,
+            args,
+            &ctx.accounts.config.to_account_info(),
+            &vault_pda,
+        )?;
+    }
+
+    emit_cpi!(TransactionExecuted {
+        config: ctx.accounts.config.key(),
+        tx_hash: expected_hash,
+        nonce: params.nonce,
+        vault_index: params.vault_index,
+        signers: params.signers,
+        programs,
+        refund: params.refund,
+    });
+
+    Ok(())
+}
+
+// marks the config as executing while the instructions run, so they can not reach `execute`,
+// `cancel` or `execute_from_module` of the same config again
+fn enter_execution(config: &mut Account<MultiSigConfig>, program_id: &Pubkey) -> Result<()> {
+    config.executing = true;
+    // persist the flag and the new nonce before the cpi, the target can be this program
+    // updating the config
+    config.exit(program_id)
+}
+
+// pick up any changes made to the config by the cpi so they are not overwritten on exit,
+// anchor skips a config the cpi closed
+fn leave_execution(config: &mut Account<MultiSigConfig>) -> Result<()> {
+    if config.to_account_info().data_is_empty() {
+        return Ok(());
+    }
+    config.reload()?;
+    config.executing = false;
+    Ok(())
+}
+
+// only the multisig program may write the config, other programs could otherwise be handed
+// a writable config, e.g. to call back into the multisig with it
+fn check_targets(
+    instructions: &[MultiSigInstruction],
+    config: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<()> {
+    for instruction in instructions {
+        require!(
+            instruction.program_id == *program_id
+                || !instruction
+                    .accounts
+                    .iter()
+                    .any(|account| account.pubkey == *config && account.is_writable),
+            errors::MultiSigErrors::WritableConfig
+        );
+    }
+    Ok(())
+}
+
+// instructions run in order, a failure in any of them reverts the whole batch
+fn invoke_instructions(
+    instructions: Vec<MultiSigInstruction>,
+    multisig_pda: &AccountInfo,
+    signer: &[&[&[u8]]],
+    remaining_accounts: &[AccountInfo],
+) -> Result<()> {
+    for instruction in instructions {
+        let accounts: Vec<AccountMeta> = instruction
             .accounts
             .iter()
             .map(|acc| AccountMeta {
                 pubkey: acc.pubkey,
-                is_signer: acc.is_signer || acc.pubkey == ctx.accounts.multisig_pda.key(),
+                is_signer: acc.is_signer || acc.pubkey == multisig_pda.key(),
                 is_writable: acc.is_writable,
             })
             .collect();
 
         let ix: Instruction = Instruction {
-            program_id: params.program_id,
+            program_id: instruction.program_id,
             accounts,
-            data: params.data,
+            data: instruction.data,
         };
 
-        let config_key = ctx.accounts.config.key();
-        // use the stored PDA seeds for the actual multisig
-        let multisig_seeds = &[
-            b"multisig-signer",
-            config_key.as_ref(),
-            &[ctx.accounts.config.pda_bump],
-        ];
+        msg!("executing {}", ix.program_id);
+        solana_program::program::invoke_signed(&ix, remaining_accounts, signer)?;
+    }
 
-        let signer = &[&multisig_seeds[..]];
+    Ok(())
+}
 
-        msg!("executing {}", ix.program_id);
-        solana_program::program::invoke_signed(&ix, ctx.remaining_accounts, signer)?;
+// signer seeds of a vault, the config's own multisig pda has no index
+fn vault_seeds<'a>(config: &'a Pubkey, index: &'a [u8; 1], bump: &'a [u8; 1]) -> Vec<&'a [u8]> {
+    let mut seeds: Vec<&[u8]> = vec![b"multisig-signer", config.as_ref()];
+    if index[0] != 0 {
+        seeds.push(index);
+    }
+    seeds.push(bump);
+    seeds
+}
 
-        Ok(())
+fn check_threshold(config: &MultiSigConfig, signers: &[Owner]) -> Result<()> {
+    check_weight(config, signers, config.threshold)
+}
+
+// a vault with its own owners only accepts them, with their weights in the config
+fn check_vault_threshold(
+    config: &MultiSigConfig,
+    vault: &Vault,
+    signers: &[Owner],
+    threshold: u8,
+) -> Result<()> {
+    require!(
+        signers.iter().all(|signer| vault.owners.contains(signer)),
+        errors::MultiSigErrors::InvalidSigner
+    );
+    check_weight(config, signers, threshold)
+}
+
+// at least one of the signers has `role`
+fn require_role(config: &MultiSigConfig, signers: &[Owner], role: u8) -> Result<()> {
+    require!(
+        signers.iter().any(|signer| config.has_role(signer, role)),
+        errors::MultiSigErrors::MissingRole
+    );
+    Ok(())
+}
+
+// check signers are unique owners and their voting weights reach the threshold
+fn check_weight(config: &MultiSigConfig, signers: &[Owner], threshold: u8) -> Result<()> {
+    unique_signers(signers)?;
+    let mut weight: u32 = 0;
+    for signer in signers.iter() {
+        let index = config
+            .owner_index(signer)
+            .map_err(|_| errors::MultiSigErrors::InvalidSigner)?;
+        // signers without a vote can still approve, e.g. as the proposer
+        if roles::has(config.roles[index], roles::VOTER) {
+            weight += config.weights[index] as u32;
+        }
     }
+    require_gte!(
+        weight,
+        threshold as u32,
+        errors::MultiSigErrors::ThresholdNotMet
+    );
+    Ok(())
+}
+
+// each Ed25519 or secp256k1 precompile instruction verifies the next signers in order,
+// together they must cover every signer
+fn verify_signatures(
+    ix_sysvar: &AccountInfo,
+    signers: &[Owner],
+    precompile_ix_indices: &[u16],
+    expected_hash: [u8; 32],
+) -> Result<()> {
+    let mut verified = 0;
+    for index in precompile_ix_indices.iter() {
+        msg!("getting instruction {}", index);
+        let ix: Instruction = load_instruction_at_checked(*index as usize, ix_sysvar)?;
+        verified += verifier::verify(&ix, *index, &signers[verified..], expected_hash)?;
+    }
+    require_eq!(
+        verified,
+        signers.len(),
+        errors::MultiSigErrors::SignatureCountMismatch
+    );
+    Ok(())
+}
+
+// vault owners are a subset of the config's owners, their weights come from the config
+fn validate_vault_owners(config: &MultiSigConfig, owners: &[Owner], threshold: u8) -> Result<()> {
+    if owners.is_empty() {
+        return Ok(());
+    }
+    unique_signers(owners)?;
+    let mut total_weight: u32 = 0;
+    for owner in owners.iter() {
+        let index = config.owner_index(owner)?;
+        if roles::has(config.roles[index], roles::VOTER) {
+            total_weight += config.weights[index] as u32;
+        }
+    }
+    require!(
+        threshold > 0 && threshold as u32 <= total_weight,
+        errors::MultiSigErrors::InvalidThreshold
+    );
+    Ok(())
+}
+
+// every vault of the config follows the instruction's accounts, ordered by index, so changes
+// to owners, weights or roles can not leave a vault's threshold out of reach
+fn validate_vaults(config: &Account<MultiSigConfig>, vaults: &[AccountInfo]) -> Result<()> {
+    require_eq!(
+        vaults.len(),
+        config.vaults as usize,
+        errors::MultiSigErrors::InvalidVault
+    );
+    let mut previous_index = 0;
+    for info in vaults.iter() {
+        require_keys_eq!(*info.owner, crate::ID, errors::MultiSigErrors::InvalidVault);
+        let vault = Vault::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        require!(
+            vault.config == config.key() && vault.index > previous_index,
+            errors::MultiSigErrors::InvalidVault
+        );
+        previous_index = vault.index;
+        validate_vault_owners(config, &vault.owners, vault.threshold)?;
+    }
+    Ok(())
+}
+
+// the admin threshold and the policies have to stay reachable by the voters, and the admin
+// threshold must not undercut the default
+fn validate_policies(config: &MultiSigConfig) -> Result<()> {
+    let voting_weight = roles::voting_weight(&config.weights, &config.roles);
+    policy::validate(&config.thresholds(), voting_weight).map_err(errors::MultiSigErrors::from)?;
+    Ok(())
 }
 
-fn unique_signers(signers: &[Pubkey]) -> Result<()> {
+fn validate_owners(owners: &[Owner], weights: &[u8], roles: &[u8], threshold: u8) -> Result<()> {
+    unique_signers(owners)?;
+    require!(!owners.is_empty(), errors::MultiSigErrors::InvalidOwnersLen);
+    require!(
+        weights.len() == owners.len() && weights.iter().all(|weight| *weight > 0),
+        errors::MultiSigErrors::InvalidWeights
+    );
+    roles::validate(weights, roles, threshold)
+}
+
+fn unique_signers(signers: &[Owner]) -> Result<()> {
     for (i, signer) in signers.iter().enumerate() {
         require!(
             !signers.iter().skip(i + 1).any(|item| item == signer),
@@ -129,37 +1094,30 @@ fn unique_signers(signers: &[Pubkey]) -> Result<()> {
     Ok(())
 }
 
-fn create_multi_sig_tx_hash(
-    multisig_pda: Pubkey,
-    nonce: u64,
-    accounts: Vec<TransactionAccount>,
-    data: &[u8],
-    program: Pubkey,
-) -> [u8; 32] {
-    let mut payload = Vec::new();
-
-    payload.extend_from_slice(&multisig_pda.to_bytes());
+// an owner is either a solana key or an ethereum address, each approves through its own precompile
+#[derive(AnchorDeserialize, AnchorSerialize, Clone, Copy, PartialEq, Eq)]
+pub enum Owner {
+    Ed25519(Pubkey),
+    Secp256k1([u8; 20]),
+}
 
-    payload.extend_from_slice(&nonce.to_le_bytes());
+impl Owner {
+    // enum tag + largest variant
+    pub const SPACE: usize = 1 + 32;
 
-    for account in accounts.iter() {
-        payload.extend_from_slice(&account.pubkey.to_bytes());
-        payload.push(account.is_signer as u8);
-        payload.push(account.is_writable as u8);
+    pub fn precompile_id(&self) -> Pubkey {
+        match self {
+            Owner::Ed25519(_) => ED25519_ID,
+            Owner::Secp256k1(_) => SECP256K1_ID,
+        }
     }
 
-    payload.extend_from_slice(&program.to_bytes());
-
-    payload.extend_from_slice(data);
-
-    keccak::hash(&payload).to_bytes()
-}
-
-#[derive(AnchorDeserialize, AnchorSerialize, Clone, Copy)]
-pub struct TransactionAccount {
-    pub pubkey: Pubkey,
-    pub is_signer: bool,
-    pub is_writable: bool,
+    pub fn public_key_bytes(&self) -> &[u8] {
+        match self {
+            Owner::Ed25519(pubkey) => pubkey.as_ref(),
+            Owner::Secp256k1(address) => address,
+        }
+    }
 }
 
 #[derive(AnchorDeserialize, AnchorSerialize)]
@@ -167,41 +1125,281 @@ pub struct ExecuteMultiSigTx {
     pub program_id: Pubkey,
     pub accounts: Vec<TransactionAccount>,
     pub data: Vec<u8>,
-    pub signers: Vec<Pubkey>,
-    pub nonce: u64,
+    pub vault_index: u8, // vault whose pda signs, 0 for the config's multisig_pda
+    pub signers: Vec<Owner>,
+    pub nonce: u64, // lane in the upper 32 bits, sequence within the lane in the lower 32 bits
+    pub valid_after: i64, // unix timestamp before which the transaction can not execute
+    pub valid_until: i64, // unix timestamp after which the transaction expires
+    // absolute indices of the Ed25519 and secp256k1 precompile instructions within the transaction
+    pub precompile_ix_indices: Vec<u16>,
+    pub refund: Option<Refund>, // paid from the multisig pda after execution, see refund
 }
 
-#[account]
+#[derive(AnchorDeserialize, AnchorSerialize)]
+pub struct ExecuteMultiSigBatchTx {
+    pub instructions: Vec<MultiSigInstruction>,
+    pub vault_index: u8, // vault whose pda signs, 0 for the config's multisig_pda
+    pub signers: Vec<Owner>,
+    pub nonce: u64, // lane in the upper 32 bits, sequence within the lane in the lower 32 bits
+    pub valid_after: i64, // unix timestamp before which the transaction can not execute
+    pub valid_until: i64, // unix timestamp after which the transaction expires
+    // absolute indices of the Ed25519 and secp256k1 precompile instructions within the transaction
+    pub precompile_ix_indices: Vec<u16>,
+    pub refund: Option<Refund>, // paid from the multisig pda after execution, see refund
+}
+
+#[derive(AnchorDeserialize, AnchorSerialize)]
+pub struct CancelMultiSigTx {
+    pub vault_index: u8, // vault whose nonce to burn, 0 for the config's multisig_pda
+    pub signers: Vec<Owner>,
+    pub nonce: u64, // the pending nonce of the vault or of a lane to burn
+    // absolute indices of the Ed25519 and secp256k1 precompile instructions within the transaction
+    pub precompile_ix_indices: Vec<u16>,
+}
+
+#[account]
 pub struct MultiSigConfig {
-    pub owners: Vec<Pubkey>,
-    pub threshold: u8,
-    pub nonce: u64,
+    pub owners: Vec<Owner>,
+    pub weights: Vec<u8>,    // voting weight of each owner, same order as owners
+    pub roles: Vec<u8>,      // roles of each owner, same order as owners, see roles
+    pub threshold: u8,       // sum of weights required to execute
+    pub admin_threshold: u8, // threshold of instructions to the multisig itself, 0 for threshold
+    pub policies: Vec<Policy>, // thresholds of other programs, see policy
+    pub nonce: u64,          // pending nonce of lane 0
+    pub chain_id: [u8; 32],  // cluster the approvals are bound to, e.g. its genesis hash
+    pub hash_version: u8,    // layout of the signed transaction hash, see tx_hash
+    pub guard: Option<Pubkey>, // program checking every execution, see guard
+    pub modules: Vec<Pubkey>, // signers allowed to execute without approvals
+    pub vaults: u8,          // number of vaults, see validate_vaults
+    pub vaults_added: u32,   // vaults ever added, the generation of the next vault's nonces
     pub multisig_pda: Pubkey, // The actual multisig PDA that will sign transactions
-    pub pda_bump: u8,         // Bump seed for the multisig PDA
+    pub pda_bump: u8,        // Bump seed for the multisig PDA
+    pub executing: bool,     // set while the instructions of a transaction run
+}
+
+impl MultiSigConfig {
+    pub fn owner_index(&self, owner: &Owner) -> Result<usize> {
+        self.owners
+            .iter()
+            .position(|item| item == owner)
+            .ok_or(errors::MultiSigErrors::OwnerNotFound.into())
+    }
+
+    pub fn has_role(&self, owner: &Owner, role: u8) -> bool {
+        self.owner_index(owner)
+            .is_ok_and(|index| roles::has(self.roles[index], role))
+    }
+
+    pub fn policy_index(&self, program_id: &Pubkey, prefix: &[u8]) -> Result<usize> {
+        self.policies
+            .iter()
+            .position(|policy| policy.program_id == *program_id && policy.prefix == prefix)
+            .ok_or(errors::MultiSigErrors::PolicyNotFound.into())
+    }
+
+    pub fn module_index(&self, module: &Pubkey) -> Result<usize> {
+        self.modules
+            .iter()
+            .position(|item| item == module)
+            .ok_or(errors::MultiSigErrors::ModuleNotEnabled.into())
+    }
+
+    pub fn thresholds(&self) -> policy::Thresholds<'_> {
+        policy::Thresholds {
+            threshold: self.threshold,
+            admin_threshold: self.admin_threshold,
+            policies: &self.policies,
+        }
+    }
+
+    pub fn space(owners: usize, modules: usize, policies: usize) -> usize {
+        8 + // discriminator
+        4 + (Owner::SPACE * owners) + // owners vec
+        4 + owners + // weights vec
+        4 + owners + // roles vec
+        1 + // threshold
+        1 + // admin_threshold
+        4 + (Policy::SPACE * policies) + // policies vec
+        8 + // nonce
+        32 + // chain_id
+        1 + // hash_version
+        1 + 32 + // guard
+        4 + (32 * modules) + // modules vec
+        1 + // vaults
+        4 + // vaults_added
+        32 + // multisig_pda
+        1 + // pda_bump
+        1 // executing
+    }
+}
+
+/// Nonces are split into lanes so unrelated proposals do not have to execute in the order
+/// they were signed. The upper 32 bits of a nonce select the lane and the lower 32 bits count
+/// within it, lane 0 is `MultiSigConfig::nonce` and every other lane lives in its own account.
+#[account]
+pub struct NonceLane {
+    pub config: Pubkey,
+    pub lane: u32,
+    pub nonce: u64, // pending nonce of the lane
+}
+
+/// A vault of a config next to its `multisig_pda`, signing as `["multisig-signer", config,
+/// index]`. Without owners it is controlled by the config's owners and threshold, otherwise
+/// only by this subset of them, counted with their weights in the config.
+///
+/// Every vault counts its own nonces, the upper 32 bits are the generation the vault was added
+/// in so approvals for a removed vault stay void once it is added back.
+#[account]
+pub struct Vault {
+    pub config: Pubkey,
+    pub index: u8,
+    pub owners: Vec<Owner>, // empty for the config's owners
+    pub threshold: u8,      // unused without owners
+    pub nonce: u64,         // pending nonce
+    pub vault_bump: u8,     // bump of the vault's signer pda
+}
+
+impl Vault {
+    pub fn space(owners: usize) -> usize {
+        8 + 32 + 1 + 4 + (Owner::SPACE * owners) + 1 + 8 + 1
+    }
+}
+
+/// Left behind by `close`, see there.
+#[account]
+pub struct ClosedConfig {}
+
+impl ClosedConfig {
+    pub const SPACE: usize = 8;
 }
 
+/// What `delegate` may spend from the multisig pda without approvals, keyed by config,
+/// delegate and mint. The mint is `Pubkey::default()` for SOL.
+#[account]
+pub struct Allowance {
+    pub config: Pubkey,
+    pub delegate: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,       // spendable per period
+    pub spent: u64,        // spent in the current period
+    pub period: i64,       // length of a period in seconds, 0 for an allowance that never resets
+    pub period_start: i64, // unix timestamp the current period started at
+    pub bump: u8,
+}
+
+impl Allowance {
+    pub const SPACE: usize = 8 + 32 + 32 + 32 + 8 + 8 + 8 + 8 + 1;
+
+    /// Moves on to the period `now` is in and books `amount` against it, see
+    /// `allowance::Budget`.
+    pub fn spend(&mut self, amount: u64, now: i64) -> Result<()> {
+        let mut budget = allowance::Budget {
+            amount: self.amount,
+            spent: self.spent,
+            period: self.period,
+            period_start: self.period_start,
+        };
+        budget
+            .spend(amount, now)
+            .map_err(errors::MultiSigErrors::from)?;
+
+        self.spent = budget.spent;
+        self.period_start = budget.period_start;
+        Ok(())
+    }
+}
+
+impl NonceLane {
+    pub const SPACE: usize = 8 + 32 + 4 + 8;
+}
+
+pub fn nonce_lane(nonce: u64) -> u32 {
+    (nonce >> 32) as u32
+}
+
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(signers: Vec<Pubkey>, threshold: u8)]
+#[instruction(signers: Vec<Owner>, weights: Vec<u8>, threshold: u8)]
 pub struct CreateMultiSigCtx<'info> {
     #[account(
         init,
         payer = payer,
-        space = 8 + // discriminator
-            4 + (32 * signers.len()) + // owners vec
-            1 + // threshold
-            8 + // nonce
-            32 + // multisig_pda
-            1, // pda_bump
+        space = MultiSigConfig::space(signers.len(), 0, 0),
         signer
     )]
     pub config: Account<'info, MultiSigConfig>,
 
+    // a config closed before can not be created again, see `close`
+    /// CHECK: only checked to be empty
+    #[account(
+        seeds = [b"closed-config", config.key().as_ref()],
+        bump,
+        constraint = closed_config.data_is_empty() @ errors::MultiSigErrors::ConfigClosed
+    )]
+    pub closed_config: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(create_key: Pubkey, signers: Vec<Owner>)]
+pub struct CreateMultiSigWithKeyCtx<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = MultiSigConfig::space(signers.len(), 0, 0),
+        seeds = [b"multisig-config", creator.key().as_ref(), create_key.as_ref()],
+        bump
+    )]
+    pub config: Account<'info, MultiSigConfig>,
+
+    // a config closed before can not be created again, see `close`
+    /// CHECK: only checked to be empty
+    #[account(
+        seeds = [b"closed-config", config.key().as_ref()],
+        bump,
+        constraint = closed_config.data_is_empty() @ errors::MultiSigErrors::ConfigClosed
+    )]
+    pub closed_config: UncheckedAccount<'info>,
+
+    pub creator: Signer<'info>,
+
     #[account(mut)]
     pub payer: Signer<'info>,
 
     pub system_program: Program<'info, System>,
 }
 
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CloseMultiSigCtx<'info> {
+    #[account(mut, has_one = multisig_pda, close = receiver)]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(
+        init,
+        payer = multisig_pda,
+        space = ClosedConfig::SPACE,
+        seeds = [b"closed-config", config.key().as_ref()],
+        bump
+    )]
+    pub closed_config: Account<'info, ClosedConfig>,
+
+    /// CHECK: only receives the rent of the config
+    #[account(mut)]
+    pub receiver: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
 #[derive(Accounts)]
 pub struct ExecuteMultiSigTxCtx<'info> {
     #[account(mut)]
@@ -209,6 +1407,7 @@ pub struct ExecuteMultiSigTxCtx<'info> {
 
     /// CHECK: This is the actual multisig PDA that will sign transactions
     #[account(
+        mut,
         seeds = [b"multisig-signer", config.key().as_ref()],
         bump = config.pda_bump,
     )]
@@ -219,4 +1418,469 @@ pub struct ExecuteMultiSigTxCtx<'info> {
     /// in the Anchor framework yet, so this is the safe approach.
     #[account(address = IX_ID)]
     pub ix_sysvar: AccountInfo<'info>,
+
+    // only needed for nonces outside of lane 0
+    #[account(mut)]
+    pub nonce_lane: Option<Account<'info, NonceLane>>,
+
+    /// CHECK: only needed when the config has a guard, checked against it in `guard_program`
+    pub guard: Option<UncheckedAccount<'info>>,
+
+    // only needed once there are executors, see `check_executor`
+    pub executor: Option<Signer<'info>>,
+
+    // only needed for vaults other than the multisig pda
+    #[account(mut)]
+    pub vault: Option<Account<'info, Vault>>,
+    /// CHECK: checked against the vault in `signing_pda`
+    #[account(mut)]
+    pub vault_pda: Option<UncheckedAccount<'info>>,
+
+    // only needed for transactions with a refund, see `refund::pay`
+    /// CHECK: checked against the signed refund
+    #[account(mut)]
+    pub refund_receiver: Option<UncheckedAccount<'info>>,
+    /// CHECK: token account of the multisig pda, token refunds only
+    #[account(mut)]
+    pub refund_vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked against the signed refund
+    pub refund_mint: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked in `allowance::transfer_tokens`
+    pub token_program: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ExecuteMultiSigTxCtx<'info> {
+    /// The config's guard program, if it has one.
+    fn guard_program(&self) -> Result<Option<AccountInfo<'info>>> {
+        let Some(guard) = self.config.guard else {
+            return Ok(None);
+        };
+        let guard_program = self
+            .guard
+            .as_ref()
+            .ok_or(errors::MultiSigErrors::InvalidGuard)?;
+        require_keys_eq!(
+            guard_program.key(),
+            guard,
+            errors::MultiSigErrors::InvalidGuard
+        );
+        Ok(Some(guard_program.to_account_info()))
+    }
+
+    /// Pays the signed refund from the vault that executed.
+    fn pay_refund(
+        &self,
+        refund: &Refund,
+        vault_pda: &AccountInfo<'info>,
+        signer: &[&[&[u8]]],
+    ) -> Result<()> {
+        refund::pay::<errors::MultiSigErrors>(
+            refund,
+            vault_pda,
+            self.refund_receiver.as_deref(),
+            self.refund_vault.as_deref(),
+            self.refund_mint.as_deref(),
+            self.token_program.as_deref(),
+            signer,
+        )
+    }
+
+    /// Once an owner is an executor only executors can submit a transaction, so approvals
+    /// that leak before execution can not be front-run.
+    fn check_executor(&self) -> Result<()> {
+        let config = &self.config;
+        if !config
+            .roles
+            .iter()
+            .any(|owner_roles| roles::has(*owner_roles, roles::EXECUTOR))
+        {
+            return Ok(());
+        }
+        let executor = self
+            .executor
+            .as_ref()
+            .ok_or(errors::MultiSigErrors::MissingRole)?;
+        require!(
+            config.has_role(&Owner::Ed25519(executor.key()), roles::EXECUTOR),
+            errors::MultiSigErrors::MissingRole
+        );
+        Ok(())
+    }
+
+    /// The `Vault` of `index`, `None` for the config's multisig pda.
+    fn vault(&self, index: u8) -> Result<Option<&Account<'info, Vault>>> {
+        if index == 0 {
+            // a vault that does not sign would be written back over what the instructions
+            // change in it
+            require!(self.vault.is_none(), errors::MultiSigErrors::InvalidVault);
+            return Ok(None);
+        }
+        let vault = self
+            .vault
+            .as_ref()
+            .ok_or(errors::MultiSigErrors::InvalidVault)?;
+        require!(
+            vault.config == self.config.key() && vault.index == index,
+            errors::MultiSigErrors::InvalidVault
+        );
+        Ok(Some(vault))
+    }
+
+    /// The pda vault `index` signs with and its bump.
+    fn signing_pda(&self, index: u8) -> Result<(AccountInfo<'info>, u8)> {
+        let Some(vault) = self.vault(index)? else {
+            return Ok((self.multisig_pda.to_account_info(), self.config.pda_bump));
+        };
+        let vault_pda = self
+            .vault_pda
+            .as_ref()
+            .ok_or(errors::MultiSigErrors::InvalidVault)?;
+        let config_key = self.config.key();
+        let expected = Pubkey::create_program_address(
+            &vault_seeds(&config_key, &[index], &[vault.vault_bump]),
+            &crate::ID,
+        )
+        .map_err(|_| errors::MultiSigErrors::InvalidVault)?;
+        require_keys_eq!(
+            vault_pda.key(),
+            expected,
+            errors::MultiSigErrors::InvalidVault
+        );
+        Ok((vault_pda.to_account_info(), vault.vault_bump))
+    }
+
+    /// The stored pending nonce of vault `index`, or for the multisig pda that of the lane
+    /// `nonce` belongs to.
+    fn pending_nonce(&mut self, index: u8, nonce: u64) -> Result<&mut u64> {
+        if self.vault(index)?.is_some() {
+            let vault = self
+                .vault
+                .as_mut()
+                .ok_or(errors::MultiSigErrors::InvalidVault)?;
+            return Ok(&mut vault.nonce);
+        }
+
+        let lane = nonce_lane(nonce);
+        if lane == 0 {
+            return Ok(&mut self.config.nonce);
+        }
+
+        let config = self.config.key();
+        let nonce_lane = self
+            .nonce_lane
+            .as_mut()
+            .ok_or(errors::MultiSigErrors::InvalidNonceLane)?;
+        require!(
+            nonce_lane.config == config && nonce_lane.lane == lane,
+            errors::MultiSigErrors::InvalidNonceLane
+        );
+        Ok(&mut nonce_lane.nonce)
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(lane: u32)]
+pub struct OpenLaneCtx<'info> {
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = NonceLane::SPACE,
+        seeds = [b"nonce-lane", config.key().as_ref(), &lane.to_le_bytes()],
+        bump
+    )]
+    pub nonce_lane: Account<'info, NonceLane>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// owner management can only be reached through `execute`, which signs for the multisig PDA
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AddOwnerCtx<'info> {
+    #[account(
+        mut,
+        has_one = multisig_pda,
+        realloc = MultiSigConfig::space(
+            config.owners.len() + 1,
+            config.modules.len(),
+            config.policies.len()
+        ),
+        realloc::payer = multisig_pda,
+        realloc::zero = false,
+    )]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemoveOwnerCtx<'info> {
+    #[account(
+        mut,
+        has_one = multisig_pda,
+        realloc = MultiSigConfig::space(
+            config.owners.len().saturating_sub(1),
+            config.modules.len(),
+            config.policies.len()
+        ),
+        realloc::payer = multisig_pda,
+        realloc::zero = false,
+    )]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EnableModuleCtx<'info> {
+    #[account(
+        mut,
+        has_one = multisig_pda,
+        realloc = MultiSigConfig::space(
+            config.owners.len(),
+            config.modules.len() + 1,
+            config.policies.len()
+        ),
+        realloc::payer = multisig_pda,
+        realloc::zero = false,
+    )]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct DisableModuleCtx<'info> {
+    #[account(
+        mut,
+        has_one = multisig_pda,
+        realloc = MultiSigConfig::space(
+            config.owners.len(),
+            config.modules.len().saturating_sub(1),
+            config.policies.len()
+        ),
+        realloc::payer = multisig_pda,
+        realloc::zero = false,
+    )]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AddPolicyCtx<'info> {
+    #[account(
+        mut,
+        has_one = multisig_pda,
+        realloc = MultiSigConfig::space(
+            config.owners.len(),
+            config.modules.len(),
+            config.policies.len() + 1
+        ),
+        realloc::payer = multisig_pda,
+        realloc::zero = false,
+    )]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemovePolicyCtx<'info> {
+    #[account(
+        mut,
+        has_one = multisig_pda,
+        realloc = MultiSigConfig::space(
+            config.owners.len(),
+            config.modules.len(),
+            config.policies.len().saturating_sub(1)
+        ),
+        realloc::payer = multisig_pda,
+        realloc::zero = false,
+    )]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ExecuteFromModuleCtx<'info> {
+    #[account(mut)]
+    pub config: Account<'info, MultiSigConfig>,
+
+    /// CHECK: This is the actual multisig PDA that will sign transactions
+    #[account(
+        seeds = [b"multisig-signer", config.key().as_ref()],
+        bump = config.pda_bump,
+    )]
+    pub multisig_pda: UncheckedAccount<'info>,
+
+    // an enabled module, usually a pda its program signs for
+    pub module: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey, mint: Pubkey)]
+pub struct SetAllowanceCtx<'info> {
+    #[account(has_one = multisig_pda)]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(
+        init_if_needed,
+        payer = multisig_pda,
+        space = Allowance::SPACE,
+        seeds = [b"allowance", config.key().as_ref(), delegate.as_ref(), mint.as_ref()],
+        bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemoveAllowanceCtx<'info> {
+    #[account(has_one = multisig_pda)]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(mut, has_one = config, close = multisig_pda)]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SpendAllowanceCtx<'info> {
+    pub config: Account<'info, MultiSigConfig>,
+
+    /// CHECK: the multisig pda the allowance is spent from
+    #[account(
+        mut,
+        seeds = [b"multisig-signer", config.key().as_ref()],
+        bump = config.pda_bump,
+    )]
+    pub multisig_pda: UncheckedAccount<'info>,
+
+    #[account(mut, has_one = config, has_one = delegate)]
+    pub allowance: Account<'info, Allowance>,
+
+    pub delegate: Signer<'info>,
+
+    /// CHECK: receives the lamports, or the tokens if it is a token account of the mint
+    #[account(mut)]
+    pub recipient: UncheckedAccount<'info>,
+
+    // token allowances only, the token program checks the vault belongs to the multisig pda
+    /// CHECK: token account of the multisig pda
+    #[account(mut)]
+    pub vault: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked against the allowance
+    pub mint: Option<UncheckedAccount<'info>>,
+    /// CHECK: checked in `allowance::transfer_tokens`
+    pub token_program: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(index: u8, owners: Vec<Owner>)]
+pub struct AddVaultCtx<'info> {
+    #[account(mut, has_one = multisig_pda)]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(
+        init,
+        payer = multisig_pda,
+        space = Vault::space(owners.len()),
+        seeds = [b"vault", config.key().as_ref(), &[index]],
+        bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(owners: Vec<Owner>)]
+pub struct ChangeVaultOwnersCtx<'info> {
+    #[account(has_one = multisig_pda)]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(
+        mut,
+        has_one = config,
+        realloc = Vault::space(owners.len()),
+        realloc::payer = multisig_pda,
+        realloc::zero = false,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemoveVaultCtx<'info> {
+    #[account(mut, has_one = multisig_pda)]
+    pub config: Account<'info, MultiSigConfig>,
+
+    #[account(mut, has_one = config, close = multisig_pda)]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut)]
+    pub multisig_pda: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateConfigCtx<'info> {
+    #[account(mut, has_one = multisig_pda)]
+    pub config: Account<'info, MultiSigConfig>,
+
+    pub multisig_pda: Signer<'info>,
 }
@@ -0,0 +1,73 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::MultiSigErrors;
+
+/// The approval of the owner counts towards the threshold.
+pub const VOTER: u8 = 1 << 0;
+/// Every transaction needs the approval of at least one proposer.
+pub const PROPOSER: u8 = 1 << 1;
+/// Every cancel needs the approval of at least one canceller.
+pub const CANCELLER: u8 = 1 << 2;
+/// Once any owner is an executor, `execute` and `execute_batch` have to be signed by one.
+/// Executors sign the solana transaction, so only ed25519 owners can have the role.
+pub const EXECUTOR: u8 = 1 << 3;
+
+/// Roles of new owners, everything but executor as before there were roles.
+pub const DEFAULT: u8 = VOTER | PROPOSER | CANCELLER;
+const ALL: u8 = DEFAULT | EXECUTOR;
+
+pub fn has(roles: u8, role: u8) -> bool {
+    roles & role != 0
+}
+
+/// Roles follow the order of the owners. They have to leave a proposer, otherwise nothing
+/// could execute, and enough voting weight to reach the threshold.
+pub fn validate(weights: &[u8], roles: &[u8], threshold: u8) -> Result<()> {
+    require!(
+        roles.len() == weights.len() && roles.iter().all(|roles| roles & !ALL == 0),
+        MultiSigErrors::InvalidRoles
+    );
+    require!(
+        roles.iter().any(|roles| has(*roles, PROPOSER)),
+        MultiSigErrors::InvalidRoles
+    );
+    require!(
+        threshold > 0 && threshold as u32 <= voting_weight(weights, roles),
+        MultiSigErrors::InvalidThreshold
+    );
+    Ok(())
+}
+
+/// Sum of the weights of the voters.
+pub fn voting_weight(weights: &[u8], roles: &[u8]) -> u32 {
+    weights
+        .iter()
+        .zip(roles)
+        .filter(|(_, roles)| has(**roles, VOTER))
+        .map(|(weight, _)| *weight as u32)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_voters_reach_the_threshold() {
+        assert!(validate(&[1, 1, 1], &[DEFAULT; 3], 3).is_ok());
+        assert!(validate(&[1, 1, 1], &[DEFAULT, DEFAULT, PROPOSER], 3).is_err());
+        assert!(validate(&[1, 1, 1], &[DEFAULT, DEFAULT, PROPOSER], 2).is_ok());
+    }
+
+    #[test]
+    fn requires_a_proposer() {
+        assert!(validate(&[1, 1], &[VOTER, VOTER | CANCELLER], 1).is_err());
+        assert!(validate(&[1, 1], &[VOTER, PROPOSER | EXECUTOR], 1).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_roles() {
+        assert!(validate(&[1], &[DEFAULT | 1 << 4], 1).is_err());
+        assert!(validate(&[1], &[DEFAULT; 2], 1).is_err());
+    }
+}
@@ -1,44 +1,86 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::ed25519_program::ID as ED25519_ID;
 use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::secp256k1_program::ID as SECP256K1_ID;
+use precompile_parser::ed25519::{self, CURRENT_INSTRUCTION_INDEX};
+use precompile_parser::{read_bytes, secp256k1};
 
 use crate::errors::MultiSigErrors;
+use crate::Owner;
 
-const SIGNATURE_LEN: usize = 64;
-const PUBKEY_LEN: usize = 32;
-const OFFSET_METADATA_SIZE: usize = 14;
-const INSTRUCTION_INFO_SIZE: usize = 2;
 const KECCAK_LEN: usize = 32;
 
-pub fn verify(ix: &Instruction, signers: Vec<Pubkey>, multi_sig_hash: [u8; 32]) -> Result<()> {
+/// Verifies the signatures of one Ed25519 or secp256k1 precompile instruction against the
+/// leading `signers` and returns how many of them it covered.
+pub fn verify(
+    ix: &Instruction,
+    ix_index: u16,
+    signers: &[Owner],
+    multi_sig_hash: [u8; 32],
+) -> Result<usize> {
     // ref to: https://docs.anza.xyz/runtime/programs#ed25519-program
-    if ix.program_id != ED25519_ID || ix.accounts.len() != 0 {
-        return Err(MultiSigErrors::InvalidEd25519Instruction.into());
+    // and: https://docs.anza.xyz/runtime/programs#secp256k1-program
+    if !ix.accounts.is_empty() {
+        return Err(MultiSigErrors::InvalidPrecompileInstruction.into());
     }
 
-    // signatures count + padding + header * total_signers
-    let header_size = INSTRUCTION_INFO_SIZE + (OFFSET_METADATA_SIZE * signers.len());
+    let count = *ix
+        .data
+        .first()
+        .ok_or(MultiSigErrors::TruncatedPrecompileHeader)? as usize;
+    require_gte!(signers.len(), count, MultiSigErrors::SignatureCountMismatch);
 
-    // sigs are encoded just after header
-    let signatures_start = header_size;
-    // then all pubkeys
-    let pubkeys_start = signatures_start + (signers.len() * SIGNATURE_LEN);
-    // then all messages
-    let messages_start = pubkeys_start + (signers.len() * PUBKEY_LEN);
+    let records = if ix.program_id == ED25519_ID {
+        ed25519::parse(&ix.data, count)
+    } else if ix.program_id == SECP256K1_ID {
+        secp256k1::parse(&ix.data, count)
+    } else {
+        return Err(MultiSigErrors::InvalidPrecompileInstruction.into());
+    }
+    .map_err(MultiSigErrors::from)?;
 
-    for (i, signer) in signers.iter().enumerate() {
-        let pubkey_offset = pubkeys_start + (i * PUBKEY_LEN);
-        let ix_pubkey_bytes = &ix.data[pubkey_offset..pubkey_offset + PUBKEY_LEN];
-        let recovered_pubkey = Pubkey::new_from_array(ix_pubkey_bytes.try_into().unwrap());
+    // only trust data the precompile read from its own instruction, secp256k1 has no
+    // marker for the current instruction and always uses the absolute index
+    let is_own_data = |index: u16| {
+        index == ix_index || (ix.program_id == ED25519_ID && index == CURRENT_INSTRUCTION_INDEX)
+    };
 
-        require_eq!(
-            recovered_pubkey,
-            signer.key(),
+    for (offsets, signer) in records.iter().zip(signers.iter()) {
+        require!(
+            is_own_data(offsets.signature_instruction_index)
+                && is_own_data(offsets.public_key_instruction_index)
+                && is_own_data(offsets.message_instruction_index),
+            MultiSigErrors::InvalidInstructionIndex
+        );
+
+        // the signer has to be of the curve this precompile verifies
+        require_keys_eq!(
+            signer.precompile_id(),
+            ix.program_id,
+            MultiSigErrors::InvalidMessageSigner
+        );
+
+        let signer_bytes = signer.public_key_bytes();
+        let ix_pubkey_bytes = read_bytes(
+            &ix.data,
+            offsets.public_key_offset as usize,
+            signer_bytes.len(),
+        )
+        .map_err(MultiSigErrors::from)?;
+
+        require!(
+            ix_pubkey_bytes.eq(signer_bytes),
             MultiSigErrors::InvalidMessageSigner
         );
 
-        let msg_offset = messages_start + (i * KECCAK_LEN);
-        let ix_msg_bytes = &ix.data[msg_offset..msg_offset + KECCAK_LEN];
+        require_eq!(
+            offsets.message_data_size as usize,
+            KECCAK_LEN,
+            MultiSigErrors::InvalidMessage
+        );
+
+        let ix_msg_bytes = read_bytes(&ix.data, offsets.message_data_offset as usize, KECCAK_LEN)
+            .map_err(MultiSigErrors::from)?;
 
         require!(
             ix_msg_bytes.eq(&multi_sig_hash),
@@ -46,5 +88,5 @@ pub fn verify(ix: &Instruction, signers: Vec<Pubkey>, multi_sig_hash: [u8; 32])
         );
     }
 
-    return Ok(());
+    Ok(count)
 }
@@ -0,0 +1,259 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Connection, PublicKey } from "@solana/web3.js";
+import { keccak_256 } from "js-sha3";
+
+// must match the layouts in the program's tx_hash module
+export const TX_HASH_V1 = 1; // legacy, single instructions without length prefixes
+export const TX_HASH_V2 = 2; // canonical, counts and lengths before variable length fields
+
+// bits of an owner's roles, must match the program's roles module
+export const ROLE_VOTER = 1;
+export const ROLE_PROPOSER = 2;
+export const ROLE_CANCELLER = 4;
+export const ROLE_EXECUTOR = 8; // ed25519 owners only, see `roles::EXECUTOR`
+
+export type TransactionAccount = {
+  pubkey: PublicKey;
+  isSigner: boolean;
+  isWritable: boolean;
+};
+
+export type MultiSigInstruction = {
+  programId: PublicKey;
+  accounts: TransactionAccount[];
+  data: Buffer;
+};
+
+// paid from the multisig pda after execution, the default mint is SOL and the default
+// receiver whoever relays the transaction
+export type Refund = {
+  amount: anchor.BN;
+  mint: PublicKey;
+  receiver: PublicKey;
+};
+
+// binds a signature to one deployment of the program on one cluster and one layout
+export type TxHashDomain = {
+  programId: PublicKey;
+  chainId: Buffer;
+  version: number;
+};
+
+const u64ToLEBytes = (num: number | anchor.BN): Buffer => {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(BigInt(num.toString()));
+  return buf;
+};
+
+const u32ToLEBytes = (num: number): Buffer => {
+  const buf = Buffer.alloc(4);
+  buf.writeUInt32LE(num);
+  return buf;
+};
+
+const i64ToLEBytes = (num: number | anchor.BN): Buffer => {
+  const buf = Buffer.alloc(8);
+  buf.writeBigInt64LE(BigInt(num.toString()));
+  return buf;
+};
+
+// the upper 32 bits of a nonce select its lane, see `NonceLane` in the program
+export const laneNonce = (
+  lane: number,
+  sequence: number | anchor.BN = 0
+): anchor.BN => new anchor.BN(lane).shln(32).add(new anchor.BN(sequence));
+
+export const findNonceLanePda = (
+  programId: PublicKey,
+  config: PublicKey,
+  lane: number
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [Buffer.from("nonce-lane"), config.toBuffer(), u32ToLEBytes(lane)],
+    programId
+  )[0];
+
+// configs made with `create_with_key` live at a pda of their creator and create key
+export const findConfigPda = (
+  programId: PublicKey,
+  creator: PublicKey,
+  createKey: PublicKey
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [Buffer.from("multisig-config"), creator.toBuffer(), createKey.toBuffer()],
+    programId
+  )[0];
+
+export const findVaultPda = (
+  programId: PublicKey,
+  config: PublicKey,
+  index: number
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [Buffer.from("vault"), config.toBuffer(), Buffer.from([index])],
+    programId
+  )[0];
+
+// the pda a vault signs with, vault 0 is the config's multisig pda
+export const findVaultSignerPda = (
+  programId: PublicKey,
+  config: PublicKey,
+  index: number
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("multisig-signer"),
+      config.toBuffer(),
+      ...(index === 0 ? [] : [Buffer.from([index])]),
+    ],
+    programId
+  )[0];
+
+// the mint of SOL allowances is the default pubkey
+export const findAllowancePda = (
+  programId: PublicKey,
+  config: PublicKey,
+  delegate: PublicKey,
+  mint: PublicKey = PublicKey.default
+): PublicKey =>
+  PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("allowance"),
+      config.toBuffer(),
+      delegate.toBuffer(),
+      mint.toBuffer(),
+    ],
+    programId
+  )[0];
+
+// anchor's tag in front of the events emitted through `emit_cpi!`
+const EVENT_IX_TAG_LE = Buffer.from("e445a52e51cb9a1d", "hex");
+
+// events are self invocations of the program, so they are read from the inner instructions
+// instead of the logs, which can be truncated
+export function parseCpiEvents(
+  program: anchor.Program<any>,
+  tx: anchor.web3.VersionedTransactionResponse
+): anchor.Event[] {
+  const keys = tx.transaction.message.getAccountKeys({
+    accountKeysFromLookups: tx.meta.loadedAddresses,
+  });
+  return tx.meta.innerInstructions
+    .flatMap((inner) => inner.instructions)
+    .filter((ix) => keys.get(ix.programIdIndex).equals(program.programId))
+    .map((ix) => Buffer.from(anchor.utils.bytes.bs58.decode(ix.data)))
+    .filter((data) => data.subarray(0, 8).equals(EVENT_IX_TAG_LE))
+    .map((data) =>
+      program.coder.events.decode(data.subarray(8).toString("base64"))
+    )
+    .filter((event) => event !== null);
+}
+
+// the genesis hash is the default chain id, it differs between localnet, devnet and mainnet
+export async function getChainId(connection: Connection): Promise<Buffer> {
+  const genesisHash = await connection.getGenesisHash();
+  return Buffer.from(anchor.utils.bytes.bs58.decode(genesisHash));
+}
+
+const encodeAccounts = (accounts: TransactionAccount[]): Buffer[] =>
+  accounts.map((account) =>
+    Buffer.concat([
+      Buffer.from(account.pubkey.toBytes()),
+      Buffer.from([account.isSigner ? 1 : 0, account.isWritable ? 1 : 0]),
+    ])
+  );
+
+export function createMultiSigBatchTxHash(
+  domain: TxHashDomain,
+  multisigPda: PublicKey,
+  nonce: anchor.BN,
+  validAfter: anchor.BN,
+  validUntil: anchor.BN,
+  instructions: MultiSigInstruction[],
+  refund?: Refund
+): Buffer {
+  const payload: Buffer[] = [];
+
+  payload.push(Buffer.from([domain.version]));
+  payload.push(Buffer.from(domain.programId.toBytes()));
+  payload.push(domain.chainId);
+
+  payload.push(Buffer.from(multisigPda.toBytes()));
+  payload.push(u64ToLEBytes(nonce));
+  payload.push(i64ToLEBytes(validAfter));
+  payload.push(i64ToLEBytes(validUntil));
+  if (domain.version === TX_HASH_V1) {
+    // without counts a batch could pass for one instruction carrying the rest
+    if (instructions.length !== 1) {
+      throw new Error("batches are only signed with the canonical layout");
+    }
+    for (const instruction of instructions) {
+      payload.push(...encodeAccounts(instruction.accounts));
+      payload.push(Buffer.from(instruction.programId.toBytes()));
+      payload.push(instruction.data);
+    }
+  } else if (domain.version === TX_HASH_V2) {
+    payload.push(u32ToLEBytes(instructions.length));
+    for (const instruction of instructions) {
+      payload.push(Buffer.from(instruction.programId.toBytes()));
+      payload.push(u32ToLEBytes(instruction.accounts.length));
+      payload.push(...encodeAccounts(instruction.accounts));
+      payload.push(u32ToLEBytes(instruction.data.length));
+      payload.push(instruction.data);
+    }
+  } else {
+    throw new Error(`unsupported transaction hash version ${domain.version}`);
+  }
+  // only hashed when there is one, refunds need the canonical layout
+  if (refund) {
+    if (domain.version !== TX_HASH_V2) {
+      throw new Error("refunds are only signed with the canonical layout");
+    }
+    payload.push(u64ToLEBytes(refund.amount));
+    payload.push(Buffer.from(refund.mint.toBytes()));
+    payload.push(Buffer.from(refund.receiver.toBytes()));
+  }
+
+  return Buffer.from(keccak_256.arrayBuffer(Buffer.concat(payload)));
+}
+
+// what the owners sign to burn `nonce`, see `create_cancel_hash` in the program
+export function createCancelHash(
+  domain: TxHashDomain,
+  multisigPda: PublicKey,
+  nonce: anchor.BN
+): Buffer {
+  const payload = Buffer.concat([
+    Buffer.from("cancel"),
+    Buffer.from([domain.version]),
+    Buffer.from(domain.programId.toBytes()),
+    domain.chainId,
+    Buffer.from(multisigPda.toBytes()),
+    u64ToLEBytes(nonce),
+  ]);
+
+  return Buffer.from(keccak_256.arrayBuffer(payload));
+}
+
+// `execute` hashes its instruction as a batch of one
+export function createMultiSigTxHash(
+  domain: TxHashDomain,
+  multisigPda: PublicKey,
+  nonce: anchor.BN,
+  validAfter: anchor.BN,
+  validUntil: anchor.BN,
+  accounts: TransactionAccount[],
+  data: Buffer,
+  program: PublicKey,
+  refund?: Refund
+): Buffer {
+  return createMultiSigBatchTxHash(
+    domain,
+    multisigPda,
+    nonce,
+    validAfter,
+    validUntil,
+    [{ programId: program, accounts, data }],
+    refund
+  );
+}